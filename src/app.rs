@@ -1,14 +1,52 @@
 /// Runs main app with UI based on dmenu
-use crate::{cli::cmd, config, screen, ui::UserInterface};
-use std::path::{Path, PathBuf};
+use crate::{
+    backup,
+    cli::{cmd, xrandr::Xrandr},
+    config::{self, LayoutConfig},
+    export::{self, Format},
+    history::{self, History},
+    import::{self, autorandr, xrandr_script},
+    ipc,
+    screen::{self, Layout},
+    systemd,
+    ui::UserInterface,
+    ConfigCommand, DaemonCommand, ImportCommand,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 pub enum Error {
     ScreenError(screen::Error),
     ConfigError(config::Error),
     CmdError(cmd::Error),
+    IpcError(ipc::Error),
+    IoError(io::Error),
+    ImportError(import::Error),
+    UnknownLayout(String),
+    UnknownBackup(String),
     InternalError,
 }
 
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::IoError(error)
+    }
+}
+
+impl From<import::Error> for Error {
+    fn from(error: import::Error) -> Self {
+        Self::ImportError(error)
+    }
+}
+
+impl From<ipc::Error> for Error {
+    fn from(error: ipc::Error) -> Self {
+        Self::IpcError(error)
+    }
+}
+
 impl From<cmd::Error> for Error {
     fn from(error: cmd::Error) -> Self {
         Self::CmdError(error)
@@ -27,14 +65,166 @@ impl From<screen::Error> for Error {
     }
 }
 
-pub fn run(config_path: &Path, dmenu_path: Option<PathBuf>) -> Result<(), Error> {
-    let mut ui = UserInterface::new(config_path, dmenu_path)?;
+pub fn run(
+    config_path: &Path,
+    dmenu_path: Option<PathBuf>,
+    rollback_timeout: Option<u64>,
+) -> Result<(), Error> {
+    let mut ui = UserInterface::new(config_path, dmenu_path, rollback_timeout)?;
     loop {
         ui.start()?;
     }
 }
 
-// TODO: replace path with layout name and apply layout from the config.rs file
-pub fn apply_layout(layout_path: &Path) {
-    unimplemented!();
+/// Applies the layout through the daemon if it is running, or directly otherwise
+pub fn apply_layout(config_path: &Path, layout_name: &str) -> Result<(), Error> {
+    match ipc::send(&ipc::Request::Apply(layout_name.to_string())) {
+        Ok(message) => {
+            println!("{}", message);
+            Ok(())
+        }
+        Err(ipc::Error::NotRunning) => {
+            let config = LayoutConfig::try_from_toml(config_path)?;
+            if config.get(layout_name).is_none() {
+                return Err(Error::UnknownLayout(layout_name.to_string()));
+            }
+            let xrandr = Xrandr::default();
+            history::apply_by_hand(&config, layout_name, &xrandr)?;
+            Ok(())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Restores the outputs as they were before the last applied layout,
+/// through the daemon if it is running, or directly otherwise
+pub fn undo(config_path: &Path) -> Result<(), Error> {
+    match ipc::send(&ipc::Request::Undo) {
+        Ok(message) => {
+            println!("{}", message);
+            Ok(())
+        }
+        Err(ipc::Error::NotRunning) => {
+            let config = LayoutConfig::try_from_toml(config_path)?;
+            match history::undo(&config, &Xrandr::default())? {
+                Some(entry) => println!("Undone layout: {}", entry.layout),
+                None => println!("Nothing to undo"),
+            }
+            Ok(())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub fn show_history() -> Result<(), Error> {
+    for entry in History::load()?.entries.iter().rev() {
+        println!(
+            "{}  {} (before: {})",
+            history::format_time(entry.time),
+            entry.layout,
+            entry.previous.name
+        );
+    }
+    Ok(())
+}
+
+pub fn run_config_command(config_path: &Path, command: &ConfigCommand) -> Result<(), Error> {
+    let backups = backup::list(config_path)?;
+    match command {
+        ConfigCommand::Backups => {
+            for backup in backups {
+                println!("{}  {}", backup.formatted_time(), backup.name());
+            }
+        }
+        ConfigCommand::Restore { backup: name } => {
+            let backup = match name {
+                Some(name) => backups.iter().find(|backup| backup.name() == *name),
+                None => backups.first(),
+            }
+            .ok_or_else(|| Error::UnknownBackup(name.clone().unwrap_or_default()))?;
+            backup::restore(config_path, backup)?;
+            println!(
+                "Restored {} from the backup of {}",
+                config_path.to_string_lossy(),
+                backup.formatted_time()
+            );
+            match ipc::send(&ipc::Request::Reload) {
+                Ok(_) | Err(ipc::Error::NotRunning) => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the layout in the format to write it to a file used without slam-rs
+pub fn export_layout(config_path: &Path, layout_name: &str, format: Format) -> Result<(), Error> {
+    let config = LayoutConfig::try_from_toml(config_path)?;
+    let layout = config
+        .get(layout_name)
+        .ok_or_else(|| Error::UnknownLayout(layout_name.to_string()))?;
+    print!("{}", export::export(layout, format));
+    Ok(())
+}
+
+pub fn run_import_command(config_path: &Path, command: &ImportCommand) -> Result<(), Error> {
+    let layouts = match command {
+        ImportCommand::Autorandr { dir } => {
+            autorandr::import(&dir.clone().unwrap_or_else(autorandr::default_dir))?
+        }
+        ImportCommand::XrandrScript { files } => files
+            .iter()
+            .map(|file| xrandr_script::import(file))
+            .collect::<Result<_, _>>()?,
+    };
+    save_imported(config_path, layouts)
+}
+
+/// Adds the layouts to the config, keeping the existing layouts of the same names.
+/// Nothing is saved if one of them is invalid
+fn save_imported(config_path: &Path, layouts: Vec<Layout>) -> Result<(), Error> {
+    for layout in &layouts {
+        layout
+            .validate()
+            .map_err(|message| import::Error::InvalidLayout(layout.name.clone(), message))?;
+    }
+    let mut config = LayoutConfig::try_from_toml(config_path)?;
+    for layout in layouts {
+        if config.get(&layout.name).is_some() {
+            println!(
+                "Skipped layout {}: a layout with this name exists",
+                layout.name
+            );
+            continue;
+        }
+        config.add(&layout)?;
+        println!("Imported layout: {}", layout.name);
+    }
+    match ipc::send(&ipc::Request::Reload) {
+        Ok(_) | Err(ipc::Error::NotRunning) => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub fn run_daemon_command(command: &DaemonCommand) -> Result<(), Error> {
+    let request = match command {
+        DaemonCommand::Status => ipc::Request::Status,
+        DaemonCommand::Apply { layout } => ipc::Request::Apply(layout.clone()),
+        DaemonCommand::Rematch => ipc::Request::Rematch,
+        DaemonCommand::Reload => ipc::Request::Reload,
+        DaemonCommand::Pause => ipc::Request::Pause,
+        DaemonCommand::Resume => ipc::Request::Resume,
+        DaemonCommand::Install => return install_service(),
+    };
+    println!("{}", ipc::send(&request)?);
+    Ok(())
+}
+
+fn install_service() -> Result<(), Error> {
+    let unit_path = systemd::install_unit()?;
+    println!("Installed {}", unit_path.to_string_lossy());
+    println!("Make DISPLAY available to user services and enable the daemon with:");
+    println!("  systemctl --user import-environment DISPLAY XAUTHORITY");
+    println!("  systemctl --user enable --now slam-rs.service");
+    Ok(())
 }
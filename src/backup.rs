@@ -0,0 +1,133 @@
+/// Atomic writes of the files slam-rs keeps, and rolling backups of config.toml
+use crate::history;
+use std::{
+    cmp::Reverse,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const BACKUP_DIR: &str = "backups";
+const MAX_BACKUPS: usize = 10;
+
+/// Replaces the file with the contents in one step, so it is never left half written.
+/// A symlinked file is written through, keeping the link in place
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(error) => return Err(error),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Expected path to a file"))?
+        .to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_path)?;
+        if let Ok(metadata) = fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+pub struct Backup {
+    pub path: PathBuf,
+    /// Seconds since the Unix epoch
+    pub time: u64,
+}
+
+impl Backup {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn formatted_time(&self) -> String {
+        history::format_time(self.time)
+    }
+}
+
+fn backup_dir(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(BACKUP_DIR)
+}
+
+fn config_file_name(config_path: &Path) -> String {
+    config_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Backups of the config, the newest first
+pub fn list(config_path: &Path) -> io::Result<Vec<Backup>> {
+    let prefix = format!("{}.", config_file_name(config_path));
+    let entries = match fs::read_dir(backup_dir(config_path)) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+    let mut backups = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let time = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(&prefix)?
+                .parse()
+                .ok()?;
+            Some(Backup { path, time })
+        })
+        .collect::<Vec<Backup>>();
+    backups.sort_by_key(|backup| Reverse(backup.time));
+    Ok(backups)
+}
+
+/// Copies the current config to the backups and removes the oldest ones
+pub fn create(config_path: &Path) -> io::Result<()> {
+    let contents = match fs::read(config_path) {
+        Ok(contents) if !contents.is_empty() => contents,
+        Ok(_) => return Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    let dir = backup_dir(config_path);
+    fs::create_dir_all(&dir)?;
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = dir.join(format!("{}.{}", config_file_name(config_path), time));
+    // Keeps the oldest version when the config is written several times a second
+    if !path.exists() {
+        write_atomic(&path, &contents)?;
+    }
+    for backup in list(config_path)?.into_iter().skip(MAX_BACKUPS) {
+        fs::remove_file(backup.path)?;
+    }
+    Ok(())
+}
+
+/// Replaces the config with the backup, backing up the replaced config as well
+pub fn restore(config_path: &Path, backup: &Backup) -> io::Result<()> {
+    let contents = fs::read(&backup.path)?;
+    create(config_path)?;
+    write_atomic(config_path, &contents)
+}
@@ -1,10 +1,13 @@
-use crate::exit_err;
+use crate::{config, exit_err};
 use std::{
     fmt::{self, Display},
     io,
+    os::unix::process::CommandExt,
     path::PathBuf,
-    process::{Command, Stdio},
+    process::{Child, Command, ExitStatus, Stdio},
     str::{self, Utf8Error},
+    thread,
+    time::{Duration, Instant},
 };
 use which::which;
 
@@ -14,6 +17,10 @@ pub enum Error {
     Io(io::Error),
     InvalidOutput,
     EmptyOutput,
+    Timeout(Duration),
+    Failed(ExitStatus),
+    /// Reading or writing the config, the state or the history failed
+    Config(config::Error),
 }
 
 impl fmt::Display for Error {
@@ -23,6 +30,15 @@ impl fmt::Display for Error {
             Self::Io(error) => write!(f, "Failed to run the command: {}", error),
             Self::InvalidOutput => write!(f, "Output didn't match given options."),
             Self::EmptyOutput => write!(f, "Expected output, found empty value."),
+            Self::Timeout(timeout) => {
+                write!(
+                    f,
+                    "The command was killed after {} seconds.",
+                    timeout.as_secs()
+                )
+            }
+            Self::Failed(status) => write!(f, "The command failed: {}", status),
+            Self::Config(error) => write!(f, "{}", error),
         }
     }
 }
@@ -39,6 +55,12 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<config::Error> for Error {
+    fn from(error: config::Error) -> Self {
+        Error::Config(error)
+    }
+}
+
 pub fn find_executable(name: &str) -> PathBuf {
     which(name).unwrap_or_else(|_| {
         exit_err!(
@@ -82,6 +104,16 @@ pub fn run(command: &str) -> CmdResult<()> {
     Ok(())
 }
 
+/// Runs the command and fails if it exits with a non-zero status
+pub fn run_and_check(command: &str) -> CmdResult<()> {
+    let status = Command::new("bash").arg("-c").arg(command).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Failed(status))
+    }
+}
+
 pub fn run_and_fetch_output(command: &str) -> CmdResult<String> {
     let child = Command::new("bash")
         .arg("-c")
@@ -96,3 +128,83 @@ pub fn run_and_fetch_output(command: &str) -> CmdResult<String> {
         Err(Error::EmptyOutput)
     }
 }
+
+const TIMEOUT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Kills the process group the child leads, so the programs it started go with it
+fn kill_group(child: &mut Child) -> CmdResult<()> {
+    // SAFETY: kill() has no memory safety preconditions
+    if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Runs the command with extra environment variables and optional input,
+/// killing it with the programs it started if it runs longer than `timeout`
+pub fn run_with_timeout(
+    command: &str,
+    envs: &[(String, String)],
+    stdin: Option<&[u8]>,
+    timeout: Duration,
+) -> CmdResult<()> {
+    let mut child = Command::new("bash")
+        .arg("-c")
+        .arg(command)
+        .envs(envs.iter().map(|(key, value)| (key, value)))
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        // Its own process group, so e.g. `sleep 999; feh ...` is killed with bash
+        .process_group(0)
+        .spawn()?;
+    if let (Some(input), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+        // The command is free to ignore its input and exit before reading it
+        let _ = io::Write::write_all(&mut child_stdin, input);
+    }
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return if status.success() {
+                Ok(())
+            } else {
+                Err(Error::Failed(status))
+            };
+        }
+        if started.elapsed() >= timeout {
+            kill_group(&mut child)?;
+            return Err(Error::Timeout(timeout));
+        }
+        thread::sleep(TIMEOUT_POLL_INTERVAL);
+    }
+}
+
+/// Like `run_and_fetch_output`, but kills the command with all of its children
+/// if it runs longer than `timeout`
+pub fn run_and_fetch_output_with_timeout(command: &str, timeout: Duration) -> CmdResult<String> {
+    let mut child = Command::new("bash")
+        .arg("-c")
+        .arg(command)
+        .stdout(Stdio::piped())
+        // Its own process group, so the programs of a pipeline are killed with bash
+        .process_group(0)
+        .spawn()?;
+    let started = Instant::now();
+    while child.try_wait()?.is_none() {
+        if started.elapsed() >= timeout {
+            kill_group(&mut child)?;
+            return Err(Error::Timeout(timeout));
+        }
+        thread::sleep(TIMEOUT_POLL_INTERVAL);
+    }
+    let output = child.wait_with_output()?;
+    let output = str::from_utf8(&output.stdout)?;
+    if !output.is_empty() {
+        Ok(output.trim().to_string())
+    } else {
+        Err(Error::EmptyOutput)
+    }
+}
@@ -1,28 +1,34 @@
-use std::{path::PathBuf, process};
+use std::{path::PathBuf, process, time::Duration};
 
 use super::cmd::{self, Cmd, CmdResult};
+use crate::settings::MenuSettings;
 
 pub struct Dmenu {
     cmd: Cmd,
+    exit_on_cancel: bool,
 }
 
 impl Default for Dmenu {
     fn default() -> Self {
-        Self::new(None, None)
+        Self::new(None, &MenuSettings::default())
     }
 }
 
 impl Dmenu {
-    pub fn new(bin_path: Option<PathBuf>, args: Option<&[String]>) -> Self {
+    /// Runs the program of the settings unless the path to another one is given
+    pub fn new(bin_path: Option<PathBuf>, settings: &MenuSettings) -> Self {
         Self {
-            cmd: Cmd::new(
-                bin_path,
-                args.unwrap_or(&["-i".to_string(), "-matching fuzzy".to_string(), "-dmenu".to_string(), "-p".to_string()]),
-                "rofi",
-            ),
+            cmd: Cmd::new(bin_path, &settings.args, &settings.program),
+            exit_on_cancel: true,
         }
     }
 
+    /// Returns `cmd::Error::EmptyOutput` instead of exiting when the menu is closed
+    pub fn without_exit_on_cancel(mut self) -> Self {
+        self.exit_on_cancel = false;
+        self
+    }
+
     fn to_cmd(&self, message: &Message) -> String {
         format!(
             "printf \"{}\" | {} \"{}\"",
@@ -46,6 +52,11 @@ impl Dmenu {
         }
     }
 
+    /// Returns `cmd::Error::Timeout` if nothing is chosen in time, the menu is closed then
+    pub fn run_with_timeout(&self, message: &Message, timeout: Duration) -> CmdResult<String> {
+        cmd::run_and_fetch_output_with_timeout(&self.to_cmd(message), timeout)
+    }
+
     pub fn run_and_fetch_output(
         &self,
         message: &Message,
@@ -53,7 +64,7 @@ impl Dmenu {
     ) -> CmdResult<String> {
         // Loop until user won't choose one of the existing options or exit
         match cmd::run_and_fetch_output(&self.to_cmd(message)) {
-            Err(cmd::Error::EmptyOutput) => process::exit(0),
+            Err(cmd::Error::EmptyOutput) if self.exit_on_cancel => process::exit(0),
             Ok(output) => {
                 if !validate_output || message.contains(&output) {
                     Ok(output)
@@ -1,4 +1,7 @@
-use crate::screen::OutputModes;
+use crate::{
+    edid::{self, Edid},
+    screen::{Geometry, LiveOutput, Mode, Orientation, OutputModes},
+};
 
 use super::cmd::{self, Cmd, CmdResult};
 use regex::Regex;
@@ -55,25 +58,89 @@ impl Xrandr {
         )
     }
 
+    fn list_outputs(&self, state: &str) -> CmdResult<Vec<String>> {
+        match cmd::run_and_fetch_output(&format!("{} | grep \" {}\"", self.cmd, state)) {
+            Ok(outputs) => Ok(outputs.split('\n').flat_map(parse_screen_output).collect()),
+            // grep prints nothing if none of the outputs is in this state
+            Err(cmd::Error::EmptyOutput) => Ok(Vec::new()),
+            Err(error) => Err(error),
+        }
+    }
+
     pub fn list_connected_outputs(&self) -> CmdResult<Vec<String>> {
-        Ok(
-            cmd::run_and_fetch_output(&format!("{} | grep \" connected\"", self.cmd))?
-                .split('\n')
-                .flat_map(parse_screen_output)
-                .collect(),
-        )
+        self.list_outputs("connected")
     }
 
     pub fn list_disconnected_outputs(&self) -> CmdResult<Vec<String>> {
-        Ok(
-            cmd::run_and_fetch_output(&format!("{} | grep \" disconnected\"", self.cmd))?
-                .split('\n')
-                .flat_map(parse_screen_output)
-                .collect(),
+        self.list_outputs("disconnected")
+    }
+
+    /// Configuration of the connected outputs, sorted by name. Fails while the X server
+    /// can't be reached, instead of reporting that nothing is connected
+    pub fn get_live_outputs(&self) -> CmdResult<Vec<LiveOutput>> {
+        let output_regexp = Regex::new(
+            r"^(\S+) connected (primary )?(?:(\d+)x(\d+)\+(-?\d+)\+(-?\d+) )?(?:(normal|left|inverted|right) )?\(",
         )
+        .expect("Hardcoded regexp.");
+        let query = cmd::run_and_fetch_output(&format!("{} --query 2>/dev/null", self.cmd))?;
+        let mut outputs: Vec<LiveOutput> = Vec::new();
+        // Whether the mode lines which follow belong to the last connected output
+        let mut is_listing_modes = false;
+        for line in query.lines() {
+            if !line.starts_with(char::is_whitespace) {
+                is_listing_modes = false;
+            }
+            if let Some(captures) = output_regexp.captures(line) {
+                let number =
+                    |index: usize| captures.get(index).and_then(|n| n.as_str().parse().ok());
+                outputs.push(LiveOutput {
+                    name: captures[1].to_string(),
+                    is_primary: captures.get(2).is_some(),
+                    mode: None,
+                    geometry: match (number(3), number(4), number(5), number(6)) {
+                        (Some(width), Some(height), Some(x), Some(y)) => Some(Geometry {
+                            width: width as u32,
+                            height: height as u32,
+                            x,
+                            y,
+                        }),
+                        _ => None,
+                    },
+                    orientation: match captures.get(7).map(|rotation| rotation.as_str()) {
+                        Some("left") => Orientation::Left,
+                        Some("right") => Orientation::Right,
+                        Some("inverted") => Orientation::Inverted,
+                        _ => Orientation::Normal,
+                    },
+                });
+                is_listing_modes = true;
+            } else if is_listing_modes && line.contains('*') {
+                // The current mode line looks like `   1920x1080     60.01*+  59.97`
+                let mut fields = line.split_whitespace();
+                let resolution = fields.next().and_then(|field| field.parse().ok());
+                let rate = fields
+                    .find(|field| field.contains('*'))
+                    .and_then(|field| field.trim_end_matches(['*', '+']).parse().ok());
+                if let (Some(output), Some(resolution), Some(rate)) =
+                    (outputs.last_mut(), resolution, rate)
+                {
+                    output.mode = Some(Mode { resolution, rate });
+                }
+            }
+        }
+        outputs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(outputs)
+    }
+
+    /// EDID of every output, connected or not, which reported one
+    pub fn get_edids(&self) -> CmdResult<HashMap<String, Edid>> {
+        Ok(edid::parse_props(&cmd::run_and_fetch_output(&format!(
+            "{} --props",
+            self.cmd
+        ))?))
     }
 
     pub fn run_with_args(&self, args: &[String]) -> CmdResult<()> {
-        cmd::run(&format!("{} {}", self.cmd, args.join(" ")))
+        cmd::run_and_check(&format!("{} {}", self.cmd, args.join(" ")))
     }
 }
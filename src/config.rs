@@ -1,25 +1,39 @@
 use crate::{
+    backup,
     cli::{cmd::CmdResult, xrandr::Xrandr},
     exit_err,
-    screen::Layout,
+    hooks::{self, GlobalHooks, Stage},
+    migration::{self, CONFIG_VERSION},
+    screen::{Layout, LiveOutput},
+    settings::Settings,
+    SYSTEM_CONFIG_PATH,
 };
+use itertools::Itertools;
 use serde_derive::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    fmt, fs,
-    io::{self, Write},
+    fmt, fs, io,
+    os::unix::io::AsRawFd,
     path::{Path, PathBuf},
 };
-
-pub const CHECK_SIGN: &str = " ✓";
+use toml_edit::{DocumentMut, Item, Table};
 
 pub type Layouts = HashMap<String, Layout>;
 
+/// Directory next to the config with more layout files, e.g. one per layout
+const LAYOUTS_DIR: &str = "layouts.d";
+
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
-    TomlDe(toml::de::Error),
-    TomlSer(toml::ser::Error),
+    TomlDe(toml_edit::de::Error),
+    TomlSer(toml_edit::ser::Error),
+    TomlEdit(toml_edit::TomlError),
+    Conflict(String),
+    Invalid(String),
+    UnsupportedVersion(u32),
+    ReadOnly(String),
+    SystemConfig,
 }
 
 impl fmt::Display for Error {
@@ -28,12 +42,40 @@ impl fmt::Display for Error {
             Self::Io(error) => write!(f, "Failed to read config file: {}", error),
             Self::TomlDe(error) => write!(f, "Invalid layout config structure: {}", error),
             Self::TomlSer(error) => write!(f, "Error serializing layout config: {}", error),
+            Self::TomlEdit(error) => write!(f, "Invalid layout config structure: {}", error),
+            Self::Conflict(layout_name) => write!(
+                f,
+                "Layout {} was changed by another slam-rs process, try again",
+                layout_name
+            ),
+            Self::Invalid(message) => write!(f, "Invalid layout config: {}", message),
+            Self::ReadOnly(layout_name) => write!(
+                f,
+                "Layout {} comes from the system config {} and can only be overridden",
+                layout_name, SYSTEM_CONFIG_PATH
+            ),
+            Self::SystemConfig => write!(
+                f,
+                "The system config {} is only read, create ~/.config/slam_rs/config.toml or set $SLAM_RS_CONFIG to save layouts",
+                SYSTEM_CONFIG_PATH
+            ),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Config version {} is newer than version {} this slam-rs supports, please update slam-rs",
+                version, CONFIG_VERSION
+            ),
         }
     }
 }
 
-impl From<toml::ser::Error> for Error {
-    fn from(error: toml::ser::Error) -> Self {
+impl From<toml_edit::TomlError> for Error {
+    fn from(error: toml_edit::TomlError) -> Self {
+        Self::TomlEdit(error)
+    }
+}
+
+impl From<toml_edit::ser::Error> for Error {
+    fn from(error: toml_edit::ser::Error) -> Self {
         Self::TomlSer(error)
     }
 }
@@ -44,16 +86,61 @@ impl From<io::Error> for Error {
     }
 }
 
-impl From<toml::de::Error> for Error {
-    fn from(error: toml::de::Error) -> Self {
+impl From<toml_edit::de::Error> for Error {
+    fn from(error: toml_edit::de::Error) -> Self {
         Self::TomlDe(error)
     }
 }
 
+/// Advisory lock held while a process writes the config, so several slam-rs processes can share it.
+/// A separate file is locked, as the config itself is replaced on every write
+struct ConfigLock {
+    _file: fs::File,
+}
+
+impl ConfigLock {
+    fn acquire(config_path: &Path) -> io::Result<Self> {
+        let file_name = config_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(config_path.with_file_name(format!(".{}.lock", file_name)))?;
+        // SAFETY: the descriptor belongs to the file opened above, the lock is released when it is closed
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { _file: file })
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LayoutConfig {
     #[serde(skip_serializing, skip_deserializing)]
     pub file: PathBuf,
+    /// Layouts as they were loaded, to tell which ones other processes changed since
+    #[serde(skip_serializing, skip_deserializing)]
+    base: Layouts,
+    /// Layouts of the system config, including the ones this config overrides
+    #[serde(skip_serializing, skip_deserializing)]
+    system: Layouts,
+    /// Files of the layouts which aren't kept in this config, but in layouts.d or an included file
+    #[serde(skip_serializing, skip_deserializing)]
+    sources: HashMap<String, PathBuf>,
+    /// Version of the config schema, older configs are migrated when loaded
+    #[serde(default)]
+    pub version: u32,
+    /// Globs of more layout files like those of layouts.d, relative to the directory of the config
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Settings::is_default")]
+    pub settings: Settings,
+    #[serde(default, skip_serializing_if = "GlobalHooks::is_empty")]
+    pub hooks: GlobalHooks,
+    #[serde(default)]
     pub layouts: Layouts,
 }
 
@@ -61,6 +148,13 @@ impl LayoutConfig {
     pub fn new(config_path: &Path) -> Self {
         Self {
             file: config_path.to_path_buf(),
+            base: Layouts::new(),
+            system: Layouts::new(),
+            sources: HashMap::new(),
+            version: CONFIG_VERSION,
+            include: Vec::new(),
+            settings: Settings::default(),
+            hooks: GlobalHooks::default(),
             layouts: Layouts::new(),
         }
     }
@@ -69,12 +163,13 @@ impl LayoutConfig {
         self.layouts.get(layout_name)
     }
 
-    pub fn layout_names(&self) -> Vec<String> {
+    /// Names of the layouts, the current one is marked with the check mark
+    pub fn layout_names(&self, current: Option<&str>) -> Vec<String> {
         self.layouts
-            .iter()
-            .map(|(layout_name, layout)| {
-                if layout.is_current {
-                    format!("{}{}", layout_name, CHECK_SIGN)
+            .keys()
+            .map(|layout_name| {
+                if Some(layout_name.as_str()) == current {
+                    format!("{}{}", layout_name, self.settings.menu.check_mark)
                 } else {
                     layout_name.to_string()
                 }
@@ -82,6 +177,14 @@ impl LayoutConfig {
             .collect()
     }
 
+    /// Name of the layout chosen from `layout_names`
+    pub fn strip_check_mark(&self, choice: &str) -> String {
+        choice
+            .strip_suffix(self.settings.menu.check_mark.as_str())
+            .unwrap_or(choice)
+            .to_string()
+    }
+
     fn _create_config_file(path: &Path) -> Result<(), io::Error> {
         fs::DirBuilder::new().recursive(true).create(
             path
@@ -91,69 +194,467 @@ impl LayoutConfig {
                     path
                 ))
         )?;
-        fs::File::create(path)?;
+        fs::write(path, format!("version = {}\n", CONFIG_VERSION))?;
         Ok(())
     }
 
+    /// Loads the config, creating or migrating it on disk unless it is the system config
     pub fn try_from_toml(config_path: &Path) -> Result<Self, Error> {
-        fs::read_to_string(config_path).map_or_else(
-            |error| {
-                if error.kind() == io::ErrorKind::NotFound {
-                    Self::_create_config_file(config_path)?;
-                    Ok(Self::new(config_path))
-                } else {
-                    Err(error.into())
-                }
-            },
-            |content| {
-                Ok(if !content.is_empty() {
-                    let mut config = toml::from_str::<Self>(&content)?;
-                    config.file = config_path.to_path_buf();
-                    config
-                } else {
-                    Self::new(config_path)
-                })
-            },
-        )
+        if _is_system_config(config_path) {
+            let (config, _, _) = Self::_parse(config_path, &Self::_read(config_path)?)?;
+            return Ok(config);
+        }
+        let content = match fs::read_to_string(config_path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                Self::_create_config_file(config_path)?;
+                Self::_read(config_path)?
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let (config, _, is_migrated) = Self::_parse(config_path, &content)?;
+        if !is_migrated {
+            return Ok(config);
+        }
+        // Another process may be migrating or changing the config as well
+        let _lock = ConfigLock::acquire(config_path)?;
+        let (config, document, is_migrated) =
+            Self::_parse(config_path, &Self::_read(config_path)?)?;
+        if is_migrated {
+            Self::_write(config_path, &document)?;
+            eprintln!(
+                "Upgraded {:?} to config version {}, the previous version is kept in the backups",
+                config_path, CONFIG_VERSION
+            );
+        }
+        Ok(config)
+    }
+
+    fn _read(config_path: &Path) -> Result<String, io::Error> {
+        match fs::read_to_string(config_path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            result => result,
+        }
+    }
+
+    /// Parses the config, migrated in memory if an older version of slam-rs wrote it,
+    /// adds the layouts of its layout files and those of the system config it doesn't override.
+    /// Also returns the document to edit and whether it was migrated
+    fn _parse(config_path: &Path, content: &str) -> Result<(Self, DocumentMut, bool), Error> {
+        let (mut config, document, is_migrated) = Self::_parse_file(content)?;
+        config.file = config_path.to_path_buf();
+        for path in config._layout_files()? {
+            config._add_layouts_from(&path)?;
+        }
+        if !_is_system_config(config_path) {
+            config.system = Self::_load_system_layouts();
+        }
+        for (layout_name, layout) in &config.system {
+            config
+                .layouts
+                .entry(layout_name.clone())
+                .or_insert_with(|| layout.clone());
+        }
+        config.base = config.layouts.clone();
+        Ok((config, document, is_migrated))
+    }
+
+    fn _parse_file(content: &str) -> Result<(Self, DocumentMut, bool), Error> {
+        let mut document = content.parse::<DocumentMut>()?;
+        let is_migrated = migration::migrate(&mut document)?;
+        let config = toml_edit::de::from_document::<Self>(document.clone())?;
+        Ok((config, document, is_migrated))
+    }
+
+    pub fn layouts_dir(&self) -> PathBuf {
+        self.file
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(LAYOUTS_DIR)
+    }
+
+    /// The files of layouts.d, then the ones the include globs match, each listed once.
+    /// Glob sorts the files it matches, so the same file wins whenever two define a layout
+    fn _layout_files(&self) -> Result<Vec<PathBuf>, Error> {
+        let config_dir = glob::Pattern::escape(
+            &self
+                .file
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .to_string_lossy(),
+        );
+        let mut patterns = vec![format!("{}/{}/*.toml", config_dir, LAYOUTS_DIR)];
+        for pattern in &self.include {
+            patterns.push(match pattern.strip_prefix("~/") {
+                Some(pattern) => format!(
+                    "{}/{}",
+                    glob::Pattern::escape(
+                        &dirs::home_dir()
+                            .ok_or_else(|| Error::Invalid("cannot find home dir".to_string()))?
+                            .to_string_lossy()
+                    ),
+                    pattern
+                ),
+                None if Path::new(pattern).is_absolute() => pattern.clone(),
+                None => format!("{}/{}", config_dir, pattern),
+            });
+        }
+        let mut files = Vec::new();
+        for pattern in patterns {
+            let paths = glob::glob(&pattern)
+                .map_err(|error| Error::Invalid(format!("include {}: {}", pattern, error)))?;
+            files.extend(
+                paths
+                    .filter_map(Result::ok)
+                    .filter(|path| path.is_file() && *path != self.file),
+            );
+        }
+        Ok(files.into_iter().unique().collect())
+    }
+
+    /// Layout files only hold layouts and have no version, so they are never migrated
+    fn _parse_layout_file(content: &str) -> Result<Layouts, Error> {
+        let document = content.parse::<DocumentMut>()?;
+        Ok(toml_edit::de::from_document::<Self>(document)?.layouts)
+    }
+
+    /// A layout defined already, in the config or an earlier file, is kept
+    fn _add_layouts_from(&mut self, path: &Path) -> Result<(), Error> {
+        let invalid = |message: String| Error::Invalid(format!("{}: {}", path.display(), message));
+        let content = fs::read_to_string(path).map_err(|error| invalid(error.to_string()))?;
+        let layouts =
+            Self::_parse_layout_file(&content).map_err(|error| invalid(error.to_string()))?;
+        for (layout_name, layout) in layouts {
+            if self.layouts.contains_key(&layout_name) {
+                eprintln!(
+                    "Skipping layout {} of {}, it is defined in {} already",
+                    layout_name,
+                    path.display(),
+                    self.source(&layout_name).display()
+                );
+                continue;
+            }
+            self.sources.insert(layout_name.clone(), path.to_path_buf());
+            self.layouts.insert(layout_name, layout);
+        }
+        Ok(())
+    }
+
+    /// File the layout is kept in, the config itself for new layouts
+    pub fn source(&self, layout_name: &str) -> &Path {
+        self.sources.get(layout_name).unwrap_or(&self.file)
+    }
+
+    /// Files of layouts.d and the included ones which hold layouts
+    pub fn layout_files(&self) -> Vec<&Path> {
+        self.sources
+            .values()
+            .map(PathBuf::as_path)
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// The system config is only read, an outdated one is migrated in memory.
+    /// A broken one is skipped, so it can't keep users from loading their own config
+    fn _load_system_layouts() -> Layouts {
+        Self::_read(Path::new(SYSTEM_CONFIG_PATH))
+            .map_err(Error::from)
+            .and_then(|content| Self::_parse_file(&content))
+            .map(|(config, _, _)| config.layouts)
+            .unwrap_or_else(|error| {
+                eprintln!(
+                    "Skipping the layouts of the system config {}: {}",
+                    SYSTEM_CONFIG_PATH, error
+                );
+                Layouts::new()
+            })
+    }
+
+    /// Backs up the config and replaces it with the document
+    fn _write(path: &Path, document: &DocumentMut) -> Result<(), Error> {
+        backup::create(path)?;
+        backup::write_atomic(path, document.to_string().as_bytes())?;
+        Ok(())
     }
 
     pub fn is_empty(&self) -> bool {
         self.layouts.is_empty()
     }
 
-    pub fn apply(&mut self, layout_name: &str, xrandr: &Xrandr) -> CmdResult<()> {
-        if let Some(layout) = self.layouts.get(layout_name) {
-            xrandr.run_with_args(&layout.get_xrandr_args())?;
-            self._mark_layout_as_current(layout_name)
-                .unwrap_or_else(|error| exit_err!("{}", error));
+    /// Checks what deserializing doesn't, e.g. that outputs are placed next to outputs
+    /// which the same layout turns on
+    pub fn validate(&self) -> Result<(), Error> {
+        self.settings
+            .validate()
+            .map_err(|message| Error::Invalid(format!("settings: {}", message)))?;
+        for (layout_name, layout) in &self.layouts {
+            let invalid = |message: String| {
+                Err(Error::Invalid(format!(
+                    "layout {}: {}",
+                    layout_name, message
+                )))
+            };
+            if layout.name != *layout_name {
+                return invalid(format!("its name is {}", layout.name));
+            }
+            layout.validate().or_else(invalid)?;
         }
         Ok(())
     }
 
-    fn _mark_layout_as_current(&mut self, layout_name: &str) -> Result<(), Error> {
-        for (name, mut layout) in self.layouts.iter_mut() {
-            layout.is_current = *name == *layout_name;
-        }
-        self._overwrite_config()
+    /// The layout the live outputs are configured with, preferring the one applied last
+    /// if several layouts configure them the same way
+    pub fn current_layout(
+        &self,
+        live: &[LiveOutput],
+        last_applied: Option<&str>,
+    ) -> Option<&Layout> {
+        let matching = self
+            .layouts
+            .values()
+            .filter(|layout| layout.matches_live(live))
+            .collect::<Vec<&Layout>>();
+        matching
+            .iter()
+            .find(|layout| Some(layout.name.as_str()) == last_applied)
+            .or_else(|| matching.first())
+            .copied()
+    }
+
+    /// Applies a layout, which doesn't have to be saved in the config, with its hooks and the global ones
+    pub fn run_layout(&self, layout: &Layout, xrandr: &Xrandr) -> CmdResult<()> {
+        let timeout = self.hooks.timeout;
+        hooks::run(Stage::PreApply, &self.hooks.pre_apply, layout, timeout);
+        hooks::run(Stage::PreApply, &layout.hooks.pre_apply, layout, timeout);
+        xrandr.run_with_args(&layout.get_xrandr_args())?;
+        hooks::run(Stage::PostApply, &layout.hooks.post_apply, layout, timeout);
+        hooks::run(Stage::PostApply, &self.hooks.post_apply, layout, timeout);
+        Ok(())
     }
 
     pub fn remove(&mut self, layout_name: &str) -> Result<(), Error> {
-        self.layouts.remove(layout_name);
-        self._overwrite_config()
+        self._update_layout(layout_name, None)
     }
 
     pub fn add(&mut self, layout: &Layout) -> Result<(), Error> {
-        self.layouts.insert(layout.name.clone(), layout.clone());
-        self._overwrite_config()
+        self._update_layout(&layout.name, Some(layout))
     }
 
-    fn _overwrite_config(&self) -> Result<(), Error> {
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&self.file)
-            .expect("File created on init, or existed before otherwise");
-        file.write_all(toml::Value::try_from(&self)?.to_string().as_bytes())?;
+    /// Replaces the layout in the file it comes from, or removes it without a new one, keeping
+    /// what other processes wrote since this config was loaded, unless they changed the same layout.
+    /// Only the table of that layout is rewritten, comments and formatting elsewhere are kept
+    fn _update_layout(&mut self, layout_name: &str, layout: Option<&Layout>) -> Result<(), Error> {
+        if _is_system_config(&self.file) {
+            return Err(Error::SystemConfig);
+        }
+        let _lock = ConfigLock::acquire(&self.file)?;
+        let (mut config, document, _) = Self::_parse(&self.file, &Self::_read(&self.file)?)?;
+        if config.get(layout_name) != self.base.get(layout_name) {
+            return Err(Error::Conflict(layout_name.to_string()));
+        }
+        let path = config.source(layout_name).to_path_buf();
+        let mut document = if path == config.file {
+            document
+        } else {
+            fs::read_to_string(&path)?.parse::<DocumentMut>()?
+        };
+        let layouts = document
+            .entry("layouts")
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_mut()
+            .ok_or_else(|| Error::Invalid("layouts is not a table".to_string()))?;
+        match layout {
+            Some(layout) => {
+                let mut table = toml_edit::ser::to_document(layout)?.as_table().clone();
+                // A changed layout stays where it was with its comments, a new one goes to the end
+                let previous = layouts.get(layout_name).and_then(Item::as_table);
+                _expand_tables(&mut table, previous.and_then(Table::position));
+                if let Some(previous) = previous {
+                    *table.decor_mut() = previous.decor().clone();
+                }
+                layouts.insert(layout_name, Item::Table(table));
+                config
+                    .layouts
+                    .insert(layout_name.to_string(), layout.clone());
+            }
+            None => {
+                if layouts.remove(layout_name).is_none() && config.system.contains_key(layout_name)
+                {
+                    return Err(Error::ReadOnly(layout_name.to_string()));
+                }
+                config.sources.remove(layout_name);
+                // The system layout it overrode takes its place again
+                match config.system.get(layout_name) {
+                    Some(layout) => config
+                        .layouts
+                        .insert(layout_name.to_string(), layout.clone()),
+                    None => config.layouts.remove(layout_name),
+                };
+            }
+        }
+        if path == config.file {
+            Self::_write(&path, &document)?;
+        } else {
+            // Only the main config has backups to list and restore
+            backup::write_atomic(&path, document.to_string().as_bytes())?;
+        }
+        config.base = config.layouts.clone();
+        *self = config;
         Ok(())
     }
 }
+
+/// The system config is the fallback without a config home, which slam-rs never writes
+fn _is_system_config(path: &Path) -> bool {
+    path == Path::new(SYSTEM_CONFIG_PATH)
+}
+
+/// Turns the inline tables serialization produces into standard tables, like those written by hand
+fn _expand_tables(table: &mut Table, position: Option<usize>) {
+    for (_, item) in table.iter_mut() {
+        if let Some(inline) = item.as_inline_table_mut() {
+            *item = Item::Table(std::mem::take(inline).into_table());
+        }
+        if let Some(table) = item.as_table_mut() {
+            _expand_tables(table, position);
+        }
+    }
+    // Tables holding only other tables need no header of their own
+    table.set_implicit(!table.is_empty() && table.iter().all(|(_, item)| item.is_table()));
+    if let Some(position) = position {
+        table.set_position(position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::{Output, State};
+    use std::process;
+
+    const CONFIG: &str = r#"# Layouts of my desks
+version = 1
+
+[settings.menu]
+program = "dmenu" # rofi isn't installed here
+args = ["-i", "-p"]
+
+# At work, next to the window
+[layouts.Work]
+name = "Work" # named after the office
+
+[layouts.Work.outputs]
+
+# At home
+[layouts.Home]
+name = "Home"
+
+[layouts.Home.outputs]
+"#;
+
+    /// A config file in a directory of its own, removed when dropped
+    struct ConfigFile(PathBuf);
+
+    impl ConfigFile {
+        fn new(test_name: &str, content: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("slam-rs-{}-{}", test_name, process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("config.toml");
+            fs::write(&path, content).unwrap();
+            Self(path)
+        }
+
+        fn read(&self) -> String {
+            fs::read_to_string(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    fn layout(name: &str, output_names: &[&str]) -> Layout {
+        let mut layout = Layout {
+            name: name.to_string(),
+            ..Layout::new()
+        };
+        for output_name in output_names {
+            layout.add(Output {
+                name: output_name.to_string(),
+                state: State::Connected,
+                ..Output::new()
+            });
+        }
+        layout
+    }
+
+    #[test]
+    fn adds_and_removes_layouts_keeping_the_rest_of_the_file() {
+        let file = ConfigFile::new("add-remove", CONFIG);
+        let mut config = LayoutConfig::try_from_toml(&file.0).unwrap();
+
+        config.add(&layout("Office", &["HDMI-1"])).unwrap();
+        let written = file.read();
+        assert!(written.starts_with(CONFIG));
+        assert!(written[CONFIG.len()..].contains("[layouts.Office.outputs.HDMI-1]"));
+        assert!(config.get("Office").is_some());
+
+        config.remove("Office").unwrap();
+        assert_eq!(file.read().trim_end(), CONFIG.trim_end());
+        assert!(config.get("Office").is_none());
+    }
+
+    #[test]
+    fn replaces_layouts_in_place() {
+        let file = ConfigFile::new("replace", CONFIG);
+        let mut config = LayoutConfig::try_from_toml(&file.0).unwrap();
+
+        config.add(&layout("Work", &["eDP-1", "DP-1"])).unwrap();
+        let written = file.read();
+        let (before, after) = written.split_once("[layouts.Work]").unwrap();
+        // Everything before the layout is kept with its comments, including the one of the layout
+        assert_eq!(before, CONFIG.split_once("[layouts.Work]").unwrap().0);
+        assert!(after.contains("[layouts.Work.outputs.DP-1]"));
+        assert!(after.contains("[layouts.Work.outputs.eDP-1]"));
+        // The next layout stays after it, as it was
+        let home = CONFIG.split_once("# At home").unwrap().1;
+        assert!(after.ends_with(home));
+        assert!(after.find("[layouts.Work.outputs.eDP-1]") < after.find("# At home"));
+
+        let reloaded = LayoutConfig::try_from_toml(&file.0).unwrap();
+        assert_eq!(
+            reloaded.get("Work"),
+            Some(&layout("Work", &["eDP-1", "DP-1"]))
+        );
+        assert_eq!(reloaded.settings.menu.program, "dmenu");
+    }
+
+    #[test]
+    fn backs_up_only_the_main_config() {
+        let file = ConfigFile::new("layout-file", CONFIG);
+        let layouts_dir = file.0.with_file_name(LAYOUTS_DIR);
+        fs::create_dir_all(&layouts_dir).unwrap();
+        let layout_file = layouts_dir.join("desk.toml");
+        fs::write(
+            &layout_file,
+            "[layouts.Desk]\nname = \"Desk\"\n\n[layouts.Desk.outputs]\n",
+        )
+        .unwrap();
+        let mut config = LayoutConfig::try_from_toml(&file.0).unwrap();
+
+        config.add(&layout("Desk", &["DP-1"])).unwrap();
+        assert!(fs::read_to_string(&layout_file)
+            .unwrap()
+            .contains("[layouts.Desk.outputs.DP-1]"));
+        assert_eq!(file.read(), CONFIG);
+        assert!(!layouts_dir.join("backups").exists());
+        assert!(backup::list(&file.0).unwrap().is_empty());
+    }
+}
@@ -1,148 +1,844 @@
-// Runs xrandr, parses its output,
-// saves to file, and offers to apply automatically detected layout
+/// Applies a layout whenever the set of connected outputs changes, restores it after resumes
+/// and X server resets, and answers the requests of the control socket
 use crate::{
     cli::cmd::CmdResult,
-    cli::{cmd, xrandr::Xrandr},
-    config::LayoutConfig,
-    exit_err, find_config_path,
-    screen::{Layout, Mode, Orientation, Output, Outputs, Position, State},
+    cli::xrandr::Xrandr,
+    config::{self, LayoutConfig},
+    edid::Edid,
+    exit_err,
+    fallback::{self, Policy},
+    find_config_path, history,
+    hooks::{self, Event, OutputIdentity, Payload},
+    ipc::{Request, Response, Server},
+    lid::Lid,
+    notifications::Notifier,
+    paths::{self, PidFile},
+    screen::{Layout, LiveOutput, Output, OutputModes, State},
+    settings::Settings,
+    sleep::ResumeWatcher,
+    state::{self, AppState, Choice},
+    systemd,
+    ui::UserInterface,
+    watch::ConfigWatcher,
+    DaemonArgs, SYSTEM_CONFIG_PATH,
 };
 use daemonize::Daemonize;
 use std::{
-    fs::{File, OpenOptions},
-    io::Write,
-    thread, time,
+    collections::{HashMap, VecDeque},
+    fs::File,
+    path::Path,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
 };
 
-const SAVE_DELAY: u64 = 3;
-const LAYOUT_FILE: &str = "/tmp/layout.toml";
-
-fn detect_outputs(xrandr: &Xrandr) -> CmdResult<()> {
-    let old_outputs_len =
-        cmd::run_and_fetch_output(&format!("cat {} | grep \"Connected\"", LAYOUT_FILE))
-            .unwrap_or_else(|_| String::new())
-            .split('\n')
-            .count();
-    let new_outputs_len = xrandr.count_connected_outputs().unwrap();
-    if old_outputs_len != new_outputs_len {
-        let internal_output = "eDP-1";
-        let output_modes = xrandr.get_output_modes()?;
-        let disconnected_outputs = xrandr.list_disconnected_outputs()?;
-        let mut is_primary = true;
-        let mut layout = Layout {
-            name: "AutoGenerated".to_string(),
-            is_current: false,
-            outputs: Outputs::with_capacity(output_modes.len() + disconnected_outputs.len()),
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+pub struct Options {
+    pub settle_time: Duration,
+    pub poll_interval: Duration,
+    pub max_applies_per_minute: usize,
+    pub fallback: Policy,
+    pub save_fallback: bool,
+    pub ask: bool,
+    pub notify: bool,
+}
+
+impl Options {
+    /// The flags given to the daemon take precedence over the settings
+    pub fn new(args: &DaemonArgs, settings: &Settings) -> Self {
+        let settings = &settings.daemon;
+        Self {
+            settle_time: Duration::from_secs(args.settle.unwrap_or(settings.settle)),
+            poll_interval: Duration::from_millis(
+                args.poll_interval.unwrap_or(settings.poll_interval),
+            ),
+            max_applies_per_minute: args.max_applies.unwrap_or(settings.max_applies),
+            fallback: args.fallback.unwrap_or(settings.fallback),
+            save_fallback: args.save_fallback().unwrap_or(settings.save_fallback),
+            ask: args.ask().unwrap_or(settings.ask),
+            notify: args.notify().unwrap_or(settings.notify),
+        }
+    }
+}
+
+/// Tracks the set of connected outputs and decides when it has settled
+/// long enough to select a layout for it
+struct Hotplug {
+    applied: Option<Vec<String>>,
+    pending: Option<(Vec<String>, Instant)>,
+    applies: VecDeque<Instant>,
+    /// Connected outputs the last hotplug events were emitted for
+    reported: Option<Vec<String>>,
+    /// Connected outputs whose layout selection was skipped by the rate limit
+    limited: Option<Vec<String>>,
+}
+
+impl Hotplug {
+    fn new() -> Self {
+        Self {
+            applied: None,
+            pending: None,
+            applies: VecDeque::new(),
+            reported: None,
+            limited: None,
+        }
+    }
+
+    /// Returns the outputs connected and disconnected since the last call,
+    /// nothing is reported for the outputs found on startup
+    fn changes(&mut self, connected: &[String]) -> (Vec<String>, Vec<String>) {
+        let previous = match self.reported.replace(connected.to_vec()) {
+            Some(previous) => previous,
+            None => return (Vec::new(), Vec::new()),
         };
-        for (output_name, output_mode) in &output_modes {
-            let output = Output {
-                name: output_name.clone(),
-                mode: Mode {
-                    resolution: output_mode.resolutions[0],
-                    rate: output_mode.rates[0],
-                },
-                is_primary,
-                state: if output_name == internal_output {
-                    State::Connected
-                } else {
-                    State::Duplicated(internal_output.to_string())
-                },
-                position: Position::Center,
-                orientation: Orientation::Normal,
-            };
-            layout.add(output);
-            is_primary = false;
+        (
+            connected
+                .iter()
+                .filter(|output_name| !previous.contains(output_name))
+                .cloned()
+                .collect(),
+            previous
+                .into_iter()
+                .filter(|output_name| !connected.contains(output_name))
+                .collect(),
+        )
+    }
+
+    /// Returns the connected outputs once they have been stable for `settle_time`
+    /// and differ from the ones the last layout was applied for
+    fn settled(&mut self, connected: Vec<String>, settle_time: Duration) -> Option<Vec<String>> {
+        if self.applied.as_ref() == Some(&connected) {
+            // A flaky cable toggled back before the set settled
+            self.pending = None;
+            return None;
+        }
+        match &self.pending {
+            Some((pending, since)) if *pending == connected => {
+                (since.elapsed() >= settle_time).then_some(connected)
+            }
+            _ => {
+                self.pending = Some((connected, Instant::now()));
+                None
+            }
+        }
+    }
+
+    fn is_rate_limited(&mut self, max_applies: usize) -> bool {
+        while matches!(self.applies.front(), Some(applied_at) if applied_at.elapsed() >= RATE_LIMIT_WINDOW)
+        {
+            self.applies.pop_front();
+        }
+        self.applies.len() >= max_applies
+    }
+
+    /// Returns true the first time the rate limit skips the connected outputs
+    fn is_newly_limited(&mut self, connected: &[String]) -> bool {
+        if self.limited.as_deref() == Some(connected) {
+            return false;
+        }
+        self.limited = Some(connected.to_vec());
+        true
+    }
+
+    fn mark_applied(&mut self, connected: Vec<String>) {
+        self.applies.push_back(Instant::now());
+        self.mark_handled(connected);
+    }
+
+    /// Marks the connected outputs as handled without applying a layout for them
+    fn mark_handled(&mut self, connected: Vec<String>) {
+        self.applied = Some(connected);
+        self.pending = None;
+        self.limited = None;
+    }
+}
+
+/// The menu asking what to do with a set of connected outputs
+struct Prompt {
+    connected: Vec<String>,
+    setup: String,
+    answer: Receiver<Result<Option<Choice>, String>>,
+}
+
+struct Daemon {
+    xrandr: Xrandr,
+    config: LayoutConfig,
+    args: DaemonArgs,
+    options: Options,
+    hotplug: Hotplug,
+    is_paused: bool,
+    current_layout: Option<Layout>,
+    state: AppState,
+    notifier: Option<Notifier>,
+    /// How the outputs looked right after the current layout was applied
+    live: Option<Vec<LiveOutput>>,
+    /// When to compare the outputs with the current layout again
+    check_live_at: Option<Instant>,
+    /// How the outputs looked at the last poll
+    last_live: Option<Vec<LiveOutput>>,
+    resume: ResumeWatcher,
+    x_available: bool,
+    /// Without it, the config is reloaded before every layout selection
+    watcher: Option<ConfigWatcher>,
+    prompt: Option<Prompt>,
+    lid: Lid,
+}
+
+fn applied_summary(layout: &Layout) -> String {
+    let active = layout
+        .outputs
+        .values()
+        .filter(|output| !matches!(output.state, State::Disconnected))
+        .count();
+    format!(
+        "Applied layout '{}' ({} monitor{})",
+        layout.name,
+        active,
+        if active == 1 { "" } else { "s" }
+    )
+}
+
+/// Watches the config with its layout files and the system config
+fn watch_config(config: &LayoutConfig) -> Option<ConfigWatcher> {
+    let system_config = Path::new(SYSTEM_CONFIG_PATH);
+    ConfigWatcher::new(&config.file)
+        .and_then(|mut watcher| {
+            if config.file != system_config && system_config.parent().is_some_and(Path::exists) {
+                watcher.add(system_config)?;
+            }
+            if config.layouts_dir().is_dir() {
+                watcher.add_dir(&config.layouts_dir())?;
+            }
+            for layout_file in config.layout_files() {
+                watcher.add(layout_file)?;
+            }
+            Ok(watcher)
+        })
+        .map_err(|error| eprintln!("Error watching the config: {}", error))
+        .ok()
+}
+
+impl Daemon {
+    fn new(args: DaemonArgs) -> Self {
+        let config = LayoutConfig::try_from_toml(&find_config_path())
+            .and_then(|config| config.validate().map(|_| config))
+            .unwrap_or_else(|error| exit_err!("{}", error));
+        let options = Options::new(&args, &config.settings);
+        let watcher = watch_config(&config);
+        Self {
+            xrandr: Xrandr::default(),
+            config,
+            args,
+            options,
+            hotplug: Hotplug::new(),
+            is_paused: false,
+            current_layout: None,
+            state: AppState::load().unwrap_or_else(|error| exit_err!("{}", error)),
+            notifier: None,
+            live: None,
+            check_live_at: None,
+            last_live: None,
+            resume: ResumeWatcher::start(),
+            x_available: true,
+            watcher,
+            prompt: None,
+            lid: Lid::detect(),
+        }
+    }
+
+    fn list_connected_outputs(&self) -> CmdResult<Vec<String>> {
+        self.lid
+            .connected_outputs(&self.xrandr, &self.config.settings)
+    }
+
+    fn notify(&mut self, summary: &str, body: &str) {
+        if !self.options.notify {
+            return;
         }
-        for output_name in disconnected_outputs {
-            layout.add(Output {
-                name: output_name,
-                ..Output::new()
-            })
+        // Created on first use, as notifications can be turned on by reloading the config
+        self.notifier
+            .get_or_insert_with(Notifier::start)
+            .send(summary, body);
+    }
+
+    /// Runs the hooks of the event, identifying the outputs by their EDID where possible
+    fn emit(
+        &self,
+        event: Event,
+        outputs: &[String],
+        connected: &[String],
+        layout: Option<&str>,
+        error: Option<String>,
+    ) {
+        if self.config.hooks.for_event(event).is_empty() {
+            return;
+        }
+        let edids = self.xrandr.get_edids().unwrap_or_default();
+        let identify = |output_names: &[String]| {
+            output_names
+                .iter()
+                .map(|output_name| OutputIdentity {
+                    name: output_name.clone(),
+                    edid: edids.get(output_name).map(|edid| edid.identity()),
+                })
+                .collect()
+        };
+        hooks::emit(
+            &self.config.hooks,
+            &Payload {
+                event,
+                outputs: identify(outputs),
+                connected: identify(connected),
+                layout: layout.map(str::to_string),
+                error,
+            },
+        );
+    }
+
+    /// Returns the live outputs while the X server can be reached, and schedules a check
+    /// of the current layout whenever they change or the X server is back after a reset.
+    /// The outputs are compared with the layout rather than listening for RandR events,
+    /// so a reset between two polls is noticed as well, as are changes by other programs:
+    /// the daemon has to be paused to configure the outputs with xrandr directly
+    fn poll_live_outputs(&mut self) -> Option<Vec<LiveOutput>> {
+        match self.xrandr.get_live_outputs() {
+            Ok(live) => {
+                if !self.x_available {
+                    println!("X server is available again");
+                    self.x_available = true;
+                    self.schedule_live_check();
+                } else if self.last_live.as_ref().is_some_and(|last| *last != live) {
+                    // E.g. the X server reset the outputs to clone mode
+                    self.schedule_live_check();
+                }
+                self.last_live = Some(live.clone());
+                Some(live)
+            }
+            Err(error) => {
+                if self.x_available {
+                    eprintln!("X server is unavailable: {}", error);
+                    self.x_available = false;
+                }
+                None
+            }
         }
+    }
+
+    /// Gives the outputs time to come up before checking them
+    fn schedule_live_check(&mut self) {
+        self.check_live_at = Some(Instant::now() + self.options.settle_time);
+    }
 
-        let mut layout_to_apply = layout.clone();
+    /// Marks the outputs as handled without applying a layout for them, the layout applied
+    /// for the previous outputs isn't restored on them
+    fn mark_handled(&mut self, connected: Vec<String>) {
+        self.hotplug.mark_handled(connected);
+        self.current_layout = None;
+        self.live = None;
+    }
+
+    /// Applies the current layout again if the outputs no longer look the way it left them,
+    /// e.g. after a resume from sleep or a reset of the X server
+    fn check_live_state(&mut self, live: Vec<LiveOutput>) -> CmdResult<()> {
+        match self.check_live_at {
+            // A changed set of outputs is handled as a hotplug instead
+            Some(check_at) if check_at <= Instant::now() && self.hotplug.pending.is_none() => {
+                self.check_live_at = None
+            }
+            _ => return Ok(()),
+        }
+        let layout = match (&self.current_layout, &self.live) {
+            (Some(layout), Some(expected)) if *expected != live => layout.clone(),
+            _ => return Ok(()),
+        };
+        if self
+            .hotplug
+            .is_rate_limited(self.options.max_applies_per_minute)
+        {
+            println!(
+                "Skipping restoring layout: more than {} applies in the last minute",
+                self.options.max_applies_per_minute
+            );
+            return Ok(());
+        }
         println!(
-            "Detected new layout: {} with {} outputs",
-            &layout.name, new_outputs_len
+            "Outputs don't match layout {} anymore, applying it again",
+            &layout.name
         );
-        let mut config = LayoutConfig::try_from_toml(&find_config_path()).unwrap();
-        let existing_layouts = config.layouts.clone();
-        'a: for existing_layout in existing_layouts.values() {
+        let connected = self.hotplug.applied.clone().unwrap_or_default();
+        self.apply(
+            &layout,
+            connected,
+            &format!("Restored layout '{}'", layout.name),
+        )
+    }
+
+    fn detect_outputs(&mut self) -> CmdResult<()> {
+        let live = match self.poll_live_outputs() {
+            Some(live) => live,
+            // Nothing would look connected until the X server is back
+            None => return Ok(()),
+        };
+        if self.resume.has_resumed(self.options.poll_interval) {
+            println!("Resumed from sleep");
+            self.schedule_live_check();
+        }
+        let connected = match self
+            .hotplug
+            .settled(self.list_connected_outputs()?, self.options.settle_time)
+        {
+            Some(connected) => connected,
+            None => return self.check_live_state(live),
+        };
+        let (added, removed) = self.hotplug.changes(&connected);
+        if !added.is_empty() {
+            self.emit(Event::OutputConnected, &added, &connected, None, None);
+        }
+        if !removed.is_empty() {
+            self.emit(Event::OutputDisconnected, &removed, &connected, None, None);
+        }
+        if self
+            .hotplug
+            .is_rate_limited(self.options.max_applies_per_minute)
+        {
+            // The set stays pending until the limit is over, so it is only reported once
+            if self.hotplug.is_newly_limited(&connected) {
+                println!(
+                    "Skipping layout selection: more than {} applies in the last minute",
+                    self.options.max_applies_per_minute
+                );
+            }
+            return Ok(());
+        }
+        self.select_and_apply(connected)
+    }
+
+    /// Loads the config and the state again, keeping the last good ones on error
+    fn reload(&mut self) -> Result<(), config::Error> {
+        let config = LayoutConfig::try_from_toml(&self.config.file)?;
+        config.validate()?;
+        let state = AppState::load()?;
+        self.options = Options::new(&self.args, &config.settings);
+        if self.watcher.is_some() && config.layout_files() != self.config.layout_files() {
+            // Other files may be included now
+            self.watcher = watch_config(&config);
+        }
+        self.config = config;
+        self.state = state;
+        Ok(())
+    }
+
+    fn reload_if_changed(&mut self) {
+        if !self
+            .watcher
+            .as_mut()
+            .is_some_and(|watcher| watcher.has_changed())
+        {
+            return;
+        }
+        match self.reload() {
+            Ok(()) => println!("Reloaded config with {} layouts", self.config.layouts.len()),
+            Err(error) => eprintln!("Keeping the last good config: {}", error),
+        }
+    }
+
+    fn find_matching_layout(
+        &self,
+        output_modes: &HashMap<String, OutputModes>,
+        new_outputs_len: usize,
+        edids: &HashMap<String, Edid>,
+    ) -> Option<Layout> {
+        'a: for existing_layout in self.config.layouts.values() {
             let existing_layout_active_outputs = existing_layout
                 .outputs
                 .iter()
                 .filter(|(_, output)| !matches!(output.state, State::Disconnected))
                 .map(|(_, output)| output.clone())
                 .collect::<Vec<Output>>();
-            if existing_layout_active_outputs.len() != new_outputs_len {
+            if existing_layout_active_outputs.len() != new_outputs_len
+                || !existing_layout.matches_edids(edids)
+            {
                 continue 'a;
             }
             for output in &existing_layout_active_outputs {
-                if let Some(output_mode) = output_modes.get(&output.name) {
-                    if !output_mode.resolutions.contains(&output.mode.resolution)
-                        || !output_mode.rates.contains(&output.mode.rate)
-                    {
-                        continue 'a;
-                    }
-                } else {
+                if !output_modes
+                    .get(&output.name)
+                    .is_some_and(|output_mode| output_mode.supports(&output.mode))
+                {
                     continue 'a;
                 }
             }
-            println!("Found existing layout to apply: {}", &existing_layout.name);
-            layout_to_apply = existing_layout.clone();
-            break;
+            return Some(existing_layout.clone());
         }
-        if layout.name == layout_to_apply.name {
-            println!(
-                "Not found existing layout, default will be applied: {}",
-                &layout.name
+        None
+    }
+
+    /// Opens the menu in the background to ask what to do with the connected outputs,
+    /// the answer is handled by `check_prompt`
+    fn ask(&mut self, connected: &[String]) {
+        let (sender, answer) = mpsc::channel();
+        let config_path = self.config.file.clone();
+        let fallback = self.options.fallback;
+        let outputs = connected.to_vec();
+        thread::spawn(move || {
+            let choice = UserInterface::for_daemon(&config_path)
+                .map_err(|error| error.to_string())
+                .and_then(|mut ui| {
+                    ui.resolve_unknown_setup(&outputs, fallback)
+                        .map_err(|error| error.to_string())
+                });
+            let _ = sender.send(choice);
+        });
+        self.prompt = Some(Prompt {
+            connected: connected.to_vec(),
+            setup: self.setup_key(connected),
+            answer,
+        });
+    }
+
+    /// Remembers the answer of the menu once it is closed, and selects a layout with it
+    /// unless other outputs were connected in the meantime
+    fn check_prompt(&mut self) -> CmdResult<()> {
+        let answer = match self.prompt.as_ref().map(|prompt| prompt.answer.try_recv()) {
+            Some(Ok(answer)) => answer,
+            Some(Err(TryRecvError::Empty)) | None => return Ok(()),
+            Some(Err(TryRecvError::Disconnected)) => Err("The menu exited".to_string()),
+        };
+        let Some(Prompt {
+            connected, setup, ..
+        }) = self.prompt.take()
+        else {
+            return Ok(());
+        };
+        // A layout might have been created from the menu
+        self.reload()
+            .unwrap_or_else(|error| eprintln!("Keeping the last good config: {}", error));
+        let is_connected = self.setup_key(&self.list_connected_outputs()?) == setup;
+        match answer {
+            Ok(Some(choice)) => {
+                self.state
+                    .remember(&setup, choice)
+                    .unwrap_or_else(|error| eprintln!("Error saving the choice: {}", error));
+                if is_connected {
+                    return self.select_and_apply(connected);
+                }
+            }
+            Ok(None) => println!("No choice made in the menu"),
+            Err(error) => println!("No answer from the menu: {}", error),
+        }
+        if is_connected {
+            self.apply_fallback(connected)?;
+        }
+        Ok(())
+    }
+
+    /// Identifies the connected outputs and the monitors on them in the state
+    fn setup_key(&self, connected: &[String]) -> String {
+        state::setup_key(connected, &self.xrandr.get_edids().unwrap_or_default())
+    }
+
+    /// Returns the layout chosen for the set of outputs before,
+    /// either by applying it by hand or from the menu
+    fn chosen_layout(&mut self, setup: &str, edids: &HashMap<String, Edid>) -> Option<Layout> {
+        match self.state.choice(setup)? {
+            Choice::Layout(layout_name) => match self.config.get(layout_name) {
+                Some(layout) if layout.matches_edids(edids) => Some(layout.clone()),
+                // It may have been saved for other monitors after it was chosen
+                Some(_) => {
+                    println!(
+                        "Chosen layout {} is saved for other monitors, skipping it",
+                        layout_name
+                    );
+                    None
+                }
+                None => {
+                    println!("Chosen layout doesn't exist anymore: {}", layout_name);
+                    self.state
+                        .forget(setup)
+                        .unwrap_or_else(|error| eprintln!("Error saving the choice: {}", error));
+                    None
+                }
+            },
+            Choice::Fallback => None,
+        }
+    }
+
+    /// Splits the modes of the outputs into the ones of the connected outputs
+    /// and the outputs missing from `connected`, e.g. the panel of a closed laptop
+    fn available_output_modes(
+        &self,
+        connected: &[String],
+    ) -> CmdResult<(HashMap<String, OutputModes>, Vec<String>)> {
+        let (output_modes, unavailable): (HashMap<String, OutputModes>, HashMap<_, _>) = self
+            .xrandr
+            .get_output_modes()?
+            .into_iter()
+            .partition(|(output_name, modes)| modes.is_empty() || connected.contains(output_name));
+        Ok((output_modes, unavailable.into_keys().collect()))
+    }
+
+    fn select_and_apply(&mut self, connected: Vec<String>) -> CmdResult<()> {
+        println!("Detected {} connected outputs", connected.len());
+        let (output_modes, _) = self.available_output_modes(&connected)?;
+        if self.watcher.is_some() {
+            // The config is reloaded as soon as it changes, unlike the choices
+            match AppState::load() {
+                Ok(state) => self.state = state,
+                Err(error) => eprintln!("Keeping the last good state: {}", error),
+            }
+        } else {
+            self.reload()
+                .unwrap_or_else(|error| eprintln!("Keeping the last good config: {}", error));
+        }
+        let edids = self.xrandr.get_edids().unwrap_or_default();
+        let setup = state::setup_key(&connected, &edids);
+        if let Some(layout) = self.chosen_layout(&setup, &edids) {
+            println!("Found layout chosen for these outputs: {}", &layout.name);
+            return self.apply(&layout, connected, &applied_summary(&layout));
+        }
+        if let Some(layout) = self.find_matching_layout(&output_modes, connected.len(), &edids) {
+            println!("Found existing layout to apply: {}", &layout.name);
+            return self.apply(&layout, connected, &applied_summary(&layout));
+        }
+        // Only one menu is open at a time, other setups get the fallback meanwhile
+        if self.options.ask && self.prompt.is_none() && self.state.choice(&setup).is_none() {
+            println!("Asking what to do with these outputs");
+            self.ask(&connected);
+            self.mark_handled(connected);
+            return Ok(());
+        }
+        self.apply_fallback(connected)
+    }
+
+    fn apply_fallback(&mut self, connected: Vec<String>) -> CmdResult<()> {
+        let (output_modes, unavailable) = self.available_output_modes(&connected)?;
+        self.emit(
+            Event::NoMatch,
+            &connected,
+            &connected,
+            Some(&self.options.fallback.to_string()),
+            None,
+        );
+        let mut disconnected_outputs = self.xrandr.list_disconnected_outputs()?;
+        disconnected_outputs.extend(unavailable);
+        match fallback::generate(
+            self.options.fallback,
+            &output_modes,
+            &disconnected_outputs,
+            |output_name| self.config.settings.is_internal_output(output_name),
+        ) {
+            Some(layout) => {
+                println!(
+                    "Not found existing layout, {} will be applied: {}",
+                    self.options.fallback, &layout.name
+                );
+                if self.options.save_fallback {
+                    self.config.add(&layout).unwrap_or_else(|error| {
+                        eprintln!("Error saving the generated layout: {}", error)
+                    });
+                }
+                let summary = format!("No layout matched, {}", self.options.fallback.outcome());
+                self.apply(&layout, connected, &summary)
+            }
+            None => {
+                println!("Not found existing layout, outputs are left as they are");
+                self.notify(
+                    &format!("No layout matched, {}", Policy::None.outcome()),
+                    &connected.join(", "),
+                );
+                self.mark_handled(connected);
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies the layout and notifies about it with the summary
+    fn apply(&mut self, layout: &Layout, connected: Vec<String>, summary: &str) -> CmdResult<()> {
+        if let Err(error) = history::run(&self.config, layout, &self.xrandr) {
+            eprintln!("Error applying layout {}: {}", layout.name, error);
+            self.emit(
+                Event::ApplyFailed,
+                &connected,
+                &connected,
+                Some(&layout.name),
+                Some(error.to_string()),
             );
-            config.add(&layout_to_apply).unwrap();
-        }
-        config.apply(&layout_to_apply.name, xrandr).unwrap();
-        let mut layout_file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(LAYOUT_FILE)
-            .unwrap_or_else(|error| exit_err!("Error opening a layout file for write: {}", error));
-        layout_file
-            .write_all(
-                toml::Value::try_from(&layout_to_apply)
-                    .unwrap()
-                    .to_string()
-                    .as_bytes(),
-            )
-            .unwrap_or_else(|error| exit_err!("Error writing to a layout file: {}", error));
-    }
-    Ok(())
+            let rolled_back_to = self.rollback(&connected);
+            self.notify(
+                &format!("Failed to apply layout '{}'", layout.name),
+                &match rolled_back_to {
+                    Some(previous) => format!("{}\nRolled back to '{}'", error, previous),
+                    None => error.to_string(),
+                },
+            );
+            // Counted as an apply, so a layout which keeps failing is not retried in a loop
+            self.hotplug.mark_applied(connected);
+            return Err(error);
+        }
+        self.current_layout = Some(layout.clone());
+        self.live = self.xrandr.get_live_outputs().ok();
+        self.notify(summary, &connected.join(", "));
+        self.hotplug.mark_applied(connected);
+        Ok(())
+    }
+
+    /// Restores the layout applied before a failed apply and returns its name if it succeeded
+    fn rollback(&self, connected: &[String]) -> Option<String> {
+        let previous = self.current_layout.as_ref()?;
+        println!("Rolling back to layout: {}", previous.name);
+        match history::run(&self.config, previous, &self.xrandr) {
+            Ok(()) => {
+                self.emit(
+                    Event::Rollback,
+                    connected,
+                    connected,
+                    Some(&previous.name),
+                    None,
+                );
+                Some(previous.name.clone())
+            }
+            Err(error) => {
+                eprintln!("Error rolling back to layout {}: {}", previous.name, error);
+                None
+            }
+        }
+    }
+
+    fn current_layout_name(&self) -> &str {
+        self.current_layout
+            .as_ref()
+            .map_or("none", |layout| layout.name.as_str())
+    }
+
+    fn handle_request(&mut self, request: Request) -> Response {
+        println!("Received request: {}", request);
+        match request {
+            Request::Status => {
+                let live = self
+                    .xrandr
+                    .get_live_outputs()
+                    .map_err(|error| error.to_string())?;
+                Ok(format!(
+                    "state: {}\nconnected: {}\nlayout: {}\napplied: {}",
+                    if self.is_paused { "paused" } else { "running" },
+                    self.list_connected_outputs()
+                        .map_err(|error| error.to_string())?
+                        .join(", "),
+                    self.config
+                        .current_layout(
+                            &live,
+                            AppState::load()
+                                .ok()
+                                .and_then(|state| state.last_applied)
+                                .as_deref(),
+                        )
+                        .map_or("none", |layout| layout.name.as_str()),
+                    self.current_layout_name(),
+                ))
+            }
+            Request::Apply(layout_name) => {
+                self.reload().map_err(|error| error.to_string())?;
+                let layout = self
+                    .config
+                    .get(&layout_name)
+                    .cloned()
+                    .ok_or_else(|| format!("Unknown layout: {}", layout_name))?;
+                let connected = self
+                    .list_connected_outputs()
+                    .map_err(|error| error.to_string())?;
+                self.state
+                    .remember(
+                        &self.setup_key(&connected),
+                        Choice::Layout(layout_name.clone()),
+                    )
+                    .unwrap_or_else(|error| eprintln!("Error saving the choice: {}", error));
+                self.apply(&layout, connected, &applied_summary(&layout))
+                    .map_err(|error| error.to_string())?;
+                Ok(format!("Applied layout: {}", layout_name))
+            }
+            Request::Rematch => {
+                let connected = self
+                    .list_connected_outputs()
+                    .map_err(|error| error.to_string())?;
+                self.select_and_apply(connected)
+                    .map_err(|error| error.to_string())?;
+                Ok(format!("Applied layout: {}", self.current_layout_name()))
+            }
+            Request::Reload => {
+                self.reload().map_err(|error| error.to_string())?;
+                Ok(format!("Loaded {} layouts", self.config.layouts.len()))
+            }
+            Request::Undo => {
+                let entry = history::undo(&self.config, &self.xrandr)
+                    .map_err(|error| error.to_string())?
+                    .ok_or_else(|| "Nothing to undo".to_string())?;
+                self.current_layout = Some(entry.previous);
+                self.live = self.xrandr.get_live_outputs().ok();
+                Ok(format!("Undone layout: {}", entry.layout))
+            }
+            Request::Pause => {
+                self.is_paused = true;
+                Ok("Paused automatic layout selection".to_string())
+            }
+            Request::Resume => {
+                self.is_paused = false;
+                self.hotplug.pending = None;
+                Ok("Resumed automatic layout selection".to_string())
+            }
+        }
+    }
+}
+
+fn run(args: DaemonArgs, mut pid_file: PidFile) -> CmdResult<()> {
+    pid_file
+        .write_pid()
+        .unwrap_or_else(|error| exit_err!("Error writing pid file: {}", error));
+    let server =
+        Server::bind().unwrap_or_else(|error| exit_err!("Error binding socket: {}", error));
+    let mut daemon = Daemon::new(args);
+    systemd::notify("READY=1")
+        .unwrap_or_else(|error| eprintln!("Error notifying the service manager: {}", error));
+    loop {
+        server.handle_requests(|request| daemon.handle_request(request));
+        daemon.reload_if_changed();
+        if !daemon.is_paused {
+            // A failed apply has been reported to the hooks and rolled back already
+            if let Err(error) = daemon.check_prompt().and_then(|_| daemon.detect_outputs()) {
+                eprintln!("Error selecting a layout: {}", error);
+            }
+        }
+        thread::sleep(daemon.options.poll_interval);
+    }
 }
 
 // TODO: add daemon's Error and cast From<daemon::Erorr> for Error in app.rs
-// TODO: detect monitors in live using xrandr
-pub fn run_daemon() -> CmdResult<()> {
-    let stdout = File::create("/tmp/slamd.out")
+pub fn run_daemon(args: DaemonArgs) -> CmdResult<()> {
+    // Locked before forking, so a second daemon fails right away instead of in the background
+    let pid_file =
+        PidFile::lock().unwrap_or_else(|error| exit_err!("Error locking pid file: {}", error));
+
+    if args.foreground {
+        return run(args, pid_file);
+    }
+
+    let stdout = paths::stdout_file()
+        .and_then(File::create)
         .unwrap_or_else(|error| exit_err!("Error creating stdout file: {}", error));
-    let stderr = File::create("/tmp/slamd.err")
+    let stderr = paths::stderr_file()
+        .and_then(File::create)
         .unwrap_or_else(|error| exit_err!("Error creating stderr file: {}", error));
     let daemon = Daemonize::new()
-        .pid_file("/tmp/slamd.pid")
-        .chown_pid_file(true)
-        .working_directory("/tmp")
-        .umask(0o777)
+        .working_directory(
+            paths::runtime_dir()
+                .unwrap_or_else(|error| exit_err!("Error creating runtime dir: {}", error)),
+        )
+        .umask(0o077)
         .stdout(stdout)
         .stderr(stderr);
 
-    File::create(LAYOUT_FILE)
-        .unwrap_or_else(|error| exit_err!("Error creating temp layout file: {}", error));
-
     match daemon.start() {
-        Ok(_) => {
-            let xrandr = Xrandr::default();
-            loop {
-                detect_outputs(&xrandr)?;
-                thread::sleep(time::Duration::from_secs(SAVE_DELAY));
-            }
-        }
+        Ok(_) => run(args, pid_file),
         Err(error) => {
             exit_err!("Error running slamd: {}", error);
         }
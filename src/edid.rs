@@ -0,0 +1,162 @@
+/// EDID blocks read from `xrandr --props`, used to tell monitors apart regardless of the port
+use regex::Regex;
+use std::collections::HashMap;
+
+const HEADER: &str = "00ffffffffffff00";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    pub hex: String,
+}
+
+impl Edid {
+    /// Whether it is the EDID of the fingerprint, e.g. one a layout was saved with
+    pub fn matches(&self, fingerprint: &str) -> bool {
+        self.hex.eq_ignore_ascii_case(fingerprint)
+    }
+
+    fn byte(&self, index: usize) -> Option<u8> {
+        self.hex
+            .get(index * 2..index * 2 + 2)
+            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+    }
+
+    /// Three-letter PNP id of the manufacturer, e.g. `DEL`
+    pub fn manufacturer(&self) -> Option<String> {
+        let id = u16::from_be_bytes([self.byte(8)?, self.byte(9)?]);
+        [10, 5, 0]
+            .iter()
+            .map(|shift| match (id >> shift) & 0x1f {
+                letter @ 1..=26 => Some((b'A' + letter as u8 - 1) as char),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn product_code(&self) -> Option<u16> {
+        Some(u16::from_le_bytes([self.byte(10)?, self.byte(11)?]))
+    }
+
+    pub fn serial(&self) -> Option<u32> {
+        Some(u32::from_le_bytes([
+            self.byte(12)?,
+            self.byte(13)?,
+            self.byte(14)?,
+            self.byte(15)?,
+        ]))
+    }
+
+    /// Human readable identity of the monitor: manufacturer, product code and serial number
+    pub fn identity(&self) -> String {
+        match (self.manufacturer(), self.product_code(), self.serial()) {
+            (Some(manufacturer), Some(product_code), Some(serial)) => {
+                format!("{}-{:04X}-{:08X}", manufacturer, product_code, serial)
+            }
+            _ => self.hex.clone(),
+        }
+    }
+}
+
+/// Parses the EDID of every output listed by `xrandr --props`
+pub fn parse_props(props: &str) -> HashMap<String, Edid> {
+    let output_regexp =
+        Regex::new(r"^(\S+) (?:connected|disconnected)").expect("Hardcoded regexp.");
+    let hex_regexp = Regex::new(r"^[0-9a-fA-F]+$").expect("Hardcoded regexp.");
+    let mut edids = HashMap::new();
+    let mut output_name: Option<String> = None;
+    let mut edid: Option<String> = None;
+    for line in props.lines() {
+        if let Some(captures) = output_regexp.captures(line) {
+            output_name = Some(captures[1].to_string());
+            edid = None;
+            continue;
+        }
+        let line = line.trim();
+        match &mut edid {
+            None if line == "EDID:" => edid = Some(String::new()),
+            Some(hex) if hex_regexp.is_match(line) => hex.push_str(&line.to_lowercase()),
+            Some(hex) => {
+                if let (Some(output_name), true) = (&output_name, hex.starts_with(HEADER)) {
+                    edids.insert(output_name.clone(), Edid { hex: hex.clone() });
+                }
+                edid = None;
+            }
+            None => {}
+        }
+    }
+    if let (Some(output_name), Some(hex)) = (output_name, edid) {
+        if hex.starts_with(HEADER) {
+            edids.insert(output_name, Edid { hex });
+        }
+    }
+    edids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROPS: &str = "\
+Screen 0: minimum 320 x 200, current 3840 x 1080, maximum 16384 x 16384
+eDP-1 connected primary 1920x1080+0+0 (normal left inverted right x axis y axis) 344mm x 194mm
+\tEDID: 
+\t\t00ffffffffffff0006af3d2100000000
+\t\t001a0104a51f1178028d15a156529d28
+\tscaling mode: Full aspect 
+\t\tsupported: Full, Center, Full aspect
+   1920x1080     60.01*+  59.97  
+HDMI-1 connected 1920x1080+1920+0 (normal left inverted right x axis y axis) 527mm x 296mm
+\tEDID: 
+\t\t00FFFFFFFFFFFF0010AC72A04C4C4A30
+\t\t0F1B0103803C2278EA4455A9554D9D26
+\tnon-desktop: 0 
+\t\trange: (0, 1)
+   1920x1080     60.00*+  50.00    59.94  
+DP-1 disconnected (normal left inverted right x axis y axis)
+\tnon-desktop: 0 
+\t\trange: (0, 1)
+DP-2 connected (normal left inverted right x axis y axis)
+\tEDID: 
+\t\t00ffffffffffff005a633a7a0f010101
+";
+
+    #[test]
+    fn parses_edids_of_connected_outputs() {
+        let edids = parse_props(PROPS);
+        assert_eq!(edids.len(), 3);
+        assert_eq!(
+            edids["eDP-1"].hex,
+            "00ffffffffffff0006af3d2100000000001a0104a51f1178028d15a156529d28"
+        );
+        assert_eq!(
+            edids["HDMI-1"].hex,
+            "00ffffffffffff0010ac72a04c4c4a300f1b0103803c2278ea4455a9554d9d26"
+        );
+        assert!(!edids.contains_key("DP-1"));
+        // The last output of the listing has no property after its EDID
+        assert_eq!(edids["DP-2"].hex, "00ffffffffffff005a633a7a0f010101");
+    }
+
+    #[test]
+    fn skips_blocks_without_the_edid_header() {
+        let props = "HDMI-1 connected\n\tEDID: \n\t\t0123456789abcdef\n\tnon-desktop: 0 \n";
+        assert!(parse_props(props).is_empty());
+    }
+
+    #[test]
+    fn identifies_monitors() {
+        let edids = parse_props(PROPS);
+        assert_eq!(edids["HDMI-1"].manufacturer().as_deref(), Some("DEL"));
+        assert_eq!(edids["HDMI-1"].product_code(), Some(0xa072));
+        assert_eq!(edids["HDMI-1"].identity(), "DEL-A072-304A4C4C");
+        assert_eq!(edids["eDP-1"].identity(), "AUO-213D-00000000");
+        assert_eq!(edids["DP-2"].identity(), "VSC-7A3A-0101010F");
+    }
+
+    #[test]
+    fn matches_fingerprints_regardless_of_case() {
+        let edid = &parse_props(PROPS)["HDMI-1"];
+        assert!(edid.matches("00FFFFFFFFFFFF0010AC72A04C4C4A300F1B0103803C2278EA4455A9554D9D26"));
+        assert!(!edid.matches("00ffffffffffff0010ac72a04c4c4a30"));
+    }
+}
@@ -0,0 +1,270 @@
+/// Converts layouts into files used without slam-rs, e.g. by the display manager before login
+use crate::screen::{Layout, Output, Position, Rate, State};
+use clap::ValueEnum;
+use itertools::Itertools;
+use std::fmt::Write;
+
+const DEVICE_IDENTIFIER: &str = "slam-rs Device";
+const SCREEN_IDENTIFIER: &str = "slam-rs Screen";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Shell script running xrandr, e.g. for LightDM's display-setup-script or SDDM's Xsetup
+    #[default]
+    Sh,
+    /// Monitor sections for /etc/X11/xorg.conf.d, tied to the outputs of the same names
+    /// by the Device section
+    XorgConf,
+    /// The layout as slam-rs stores it
+    Json,
+}
+
+pub fn export(layout: &Layout, format: Format) -> String {
+    match format {
+        Format::Sh => to_sh(layout),
+        Format::XorgConf => to_xorg_conf(layout),
+        Format::Json => format!(
+            "{}\n",
+            serde_json::to_string_pretty(layout)
+                .expect("Layouts consist of strings and numbers only.")
+        ),
+    }
+}
+
+/// The name of the layout in a comment, which a line break in it would end
+fn header(layout: &Layout) -> String {
+    format!(
+        "# Layout {} exported by slam-rs\n",
+        layout.name.replace(['\r', '\n'], " ")
+    )
+}
+
+fn to_sh(layout: &Layout) -> String {
+    let mut args = layout
+        .get_xrandr_args()
+        .iter()
+        .map(|arg| arg.split_whitespace().join(" "))
+        .collect::<Vec<String>>();
+    args.sort();
+    format!(
+        "#!/bin/sh\n{}xrandr {}\n",
+        header(layout),
+        args.join(" \\\n    ")
+    )
+}
+
+/// Options placing the output relative to another output or at a point of the screen
+fn position_options(position: &Position) -> Vec<(&'static str, String)> {
+    match position {
+        Position::Center => Vec::new(),
+        Position::LeftOf(output) => vec![("LeftOf", output.clone())],
+        Position::RightOf(output) => vec![("RightOf", output.clone())],
+        Position::Above(output) => vec![("Above", output.clone())],
+        Position::Below(output) => vec![("Below", output.clone())],
+        Position::Absolute(point) => vec![("Position", format!("{} {}", point.x, point.y))],
+    }
+}
+
+/// Mirrored outputs take the position of the output they mirror, refresh rates and scales
+/// can't be configured in xorg.conf and are left as comments
+fn monitor_section(layout: &Layout, output: &Output) -> String {
+    let mut comments = Vec::new();
+    let mut options = Vec::new();
+    match &output.state {
+        State::Disconnected => options.push(("Enable", "false".to_string())),
+        State::Connected => options.extend(position_options(&output.position)),
+        State::Duplicated(same_as) => {
+            comments.push(format!("Same as {}", same_as));
+            if let Some(mirrored) = layout.get(same_as) {
+                options.extend(position_options(&mirrored.position));
+            }
+        }
+    }
+    if !matches!(output.state, State::Disconnected) {
+        if !output.mode.is_auto() {
+            options.push(("PreferredMode", output.mode.resolution.to_string()));
+            if output.mode.rate != Rate::default() {
+                comments.push(format!(
+                    "Refresh rate {} can't be set here",
+                    output.mode.rate
+                ));
+            }
+        }
+        if let Some(scale) = output.scale {
+            comments.push(format!("Scale {}x{} can't be set here", scale.x, scale.y));
+        }
+        options.push(("Rotate", output.orientation.to_string().to_lowercase()));
+        if output.is_primary {
+            options.push(("Primary", "true".to_string()));
+        }
+    }
+    let mut section = String::from("Section \"Monitor\"\n");
+    for comment in comments {
+        let _ = writeln!(section, "    # {}", comment);
+    }
+    let _ = writeln!(section, "    Identifier \"{}\"", output.name);
+    for (name, value) in options {
+        let _ = writeln!(section, "    Option \"{}\" \"{}\"", name, value);
+    }
+    section.push_str("EndSection\n");
+    section
+}
+
+/// Xorg applies a Monitor section to the output the `Monitor-<output>` option of the Device
+/// section names. Without a Driver line, Xorg picks the driver as it does without a config
+fn device_section(outputs: &[&Output]) -> String {
+    let mut section = format!(
+        "Section \"Device\"\n    Identifier \"{}\"\n",
+        DEVICE_IDENTIFIER
+    );
+    for output in outputs {
+        let _ = writeln!(
+            section,
+            "    Option \"Monitor-{}\" \"{}\"",
+            output.name, output.name
+        );
+    }
+    section.push_str("EndSection\n");
+    section
+}
+
+fn screen_section() -> String {
+    format!(
+        "Section \"Screen\"\n    Identifier \"{}\"\n    Device \"{}\"\nEndSection\n",
+        SCREEN_IDENTIFIER, DEVICE_IDENTIFIER
+    )
+}
+
+fn to_xorg_conf(layout: &Layout) -> String {
+    let mut outputs = layout.outputs.values().collect::<Vec<&Output>>();
+    outputs.sort_by(|output, other| output.name.cmp(&other.name));
+    format!(
+        "{}{}",
+        header(layout),
+        outputs
+            .iter()
+            .map(|output| monitor_section(layout, output))
+            .chain([device_section(&outputs), screen_section()])
+            .join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::{Mode, Point, Scale};
+
+    fn output(name: &str, state: State, position: Position) -> Output {
+        Output {
+            name: name.to_string(),
+            state,
+            position,
+            ..Output::new()
+        }
+    }
+
+    const DESK_SH: &str = r#"#!/bin/sh
+# Layout Desk exported by slam-rs
+xrandr --output DP-1 --auto --rotate normal --same-as HDMI-1 \
+    --output HDMI-1 --auto --rotate normal --right-of eDP-1 \
+    --output VGA-1 --rotate normal --off \
+    --output eDP-1 --mode 1920x1080 --rate 60 --rotate normal --pos 0x0 --primary --scale 1.5x1.5
+"#;
+
+    const DESK_XORG_CONF: &str = r#"# Layout Desk exported by slam-rs
+Section "Monitor"
+    # Same as HDMI-1
+    Identifier "DP-1"
+    Option "RightOf" "eDP-1"
+    Option "Rotate" "normal"
+EndSection
+
+Section "Monitor"
+    Identifier "HDMI-1"
+    Option "RightOf" "eDP-1"
+    Option "Rotate" "normal"
+EndSection
+
+Section "Monitor"
+    Identifier "VGA-1"
+    Option "Enable" "false"
+EndSection
+
+Section "Monitor"
+    # Refresh rate 60 can't be set here
+    # Scale 1.5x1.5 can't be set here
+    Identifier "eDP-1"
+    Option "Position" "0 0"
+    Option "PreferredMode" "1920x1080"
+    Option "Rotate" "normal"
+    Option "Primary" "true"
+EndSection
+
+Section "Device"
+    Identifier "slam-rs Device"
+    Option "Monitor-DP-1" "DP-1"
+    Option "Monitor-HDMI-1" "HDMI-1"
+    Option "Monitor-VGA-1" "VGA-1"
+    Option "Monitor-eDP-1" "eDP-1"
+EndSection
+
+Section "Screen"
+    Identifier "slam-rs Screen"
+    Device "slam-rs Device"
+EndSection
+"#;
+
+    /// eDP-1 at the origin, HDMI-1 right of it, DP-1 mirroring HDMI-1 and VGA-1 off
+    fn desk() -> Layout {
+        let mut layout = Layout {
+            name: "Desk".to_string(),
+            ..Layout::new()
+        };
+        layout.add(Output {
+            is_primary: true,
+            mode: Mode {
+                resolution: "1920x1080".parse().unwrap(),
+                rate: "60".parse().unwrap(),
+            },
+            scale: Some(Scale { x: 1.5, y: 1.5 }),
+            ..output(
+                "eDP-1",
+                State::Connected,
+                Position::Absolute(Point { x: 0, y: 0 }),
+            )
+        });
+        layout.add(output(
+            "HDMI-1",
+            State::Connected,
+            Position::RightOf("eDP-1".to_string()),
+        ));
+        layout.add(output(
+            "DP-1",
+            State::Duplicated("HDMI-1".to_string()),
+            Position::Center,
+        ));
+        layout.add(output("VGA-1", State::Disconnected, Position::Center));
+        layout
+    }
+
+    #[test]
+    fn exports_shell_scripts() {
+        assert_eq!(to_sh(&desk()), DESK_SH);
+    }
+
+    #[test]
+    fn exports_xorg_confs() {
+        assert_eq!(to_xorg_conf(&desk()), DESK_XORG_CONF);
+    }
+
+    #[test]
+    fn replaces_line_breaks_in_layout_names() {
+        let mut layout = desk();
+        layout.name = "Desk\nxrandr --off\r".to_string();
+        for format in [Format::Sh, Format::XorgConf] {
+            let exported = export(&layout, format);
+            assert!(exported.contains("# Layout Desk xrandr --off  exported by slam-rs\n"));
+            assert!(!exported.contains("\nxrandr --off"));
+        }
+    }
+}
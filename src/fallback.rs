@@ -0,0 +1,172 @@
+/// Layouts generated for setups none of the saved layouts match
+use crate::screen::{Layout, Mode, Output, OutputModes, Position, State};
+use clap::ValueEnum;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+pub const GENERATED_LAYOUT_NAME: &str = "AutoGenerated";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy {
+    /// Duplicate the internal panel on every other output
+    #[default]
+    Mirror,
+    /// Place the other outputs to the left of the internal panel
+    ExtendLeft,
+    /// Place the other outputs to the right of the internal panel
+    ExtendRight,
+    /// Turn the internal panel off and extend over the other outputs
+    ExternalOnly,
+    /// Keep only the internal panel on
+    InternalOnly,
+    /// Leave the outputs as they are
+    None,
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Mirror => "mirror",
+            Self::ExtendLeft => "extend left",
+            Self::ExtendRight => "extend right",
+            Self::ExternalOnly => "external only",
+            Self::InternalOnly => "internal only",
+            Self::None => "none",
+        })
+    }
+}
+
+impl Policy {
+    /// What the policy did to the outputs, e.g. for notifications
+    pub fn outcome(&self) -> &'static str {
+        match self {
+            Self::Mirror => "mirrored displays",
+            Self::ExtendLeft => "extended displays to the left",
+            Self::ExtendRight => "extended displays to the right",
+            Self::ExternalOnly => "turned the internal display off",
+            Self::InternalOnly => "kept only the internal display",
+            Self::None => "left displays as they are",
+        }
+    }
+}
+
+fn active_output(
+    name: &str,
+    output_modes: &OutputModes,
+    state: State,
+    position: Position,
+) -> Output {
+    Output {
+        name: name.to_string(),
+        mode: Mode {
+            resolution: output_modes.resolutions[0],
+            rate: output_modes.rates[0],
+        },
+        state,
+        position,
+        ..Output::new()
+    }
+}
+
+/// Generates a layout for the connected outputs according to the policy,
+/// or returns None if the outputs should be left as they are
+pub fn generate(
+    policy: Policy,
+    output_modes: &HashMap<String, OutputModes>,
+    disconnected_outputs: &[String],
+    is_internal_output: impl Fn(&str) -> bool,
+) -> Option<Layout> {
+    let mut connected = output_modes
+        .iter()
+        .filter(|(_, modes)| !modes.is_empty())
+        .map(|(output_name, _)| output_name.clone())
+        .collect::<Vec<String>>();
+    connected.sort();
+    let (internal, external): (Vec<String>, Vec<String>) = connected
+        .into_iter()
+        .partition(|output_name| is_internal_output(output_name));
+    let internal = internal.into_iter().next();
+    let mut layout = Layout {
+        name: GENERATED_LAYOUT_NAME.to_string(),
+        ..Layout::new()
+    };
+    let mut turned_off = disconnected_outputs.to_vec();
+
+    let mut active = match (policy, internal) {
+        (Policy::None, _) => return None,
+        (Policy::InternalOnly, Some(internal)) => {
+            turned_off.extend(external);
+            vec![(internal, State::Connected, Position::Center)]
+        }
+        (Policy::ExternalOnly, Some(internal)) if !external.is_empty() => {
+            turned_off.push(internal);
+            extend(external, Position::RightOf)
+        }
+        (Policy::Mirror, Some(internal)) => mirror(internal, external),
+        (Policy::ExtendLeft, Some(internal)) => {
+            extend([vec![internal], external].concat(), Position::LeftOf)
+        }
+        (Policy::ExtendRight, Some(internal)) => {
+            extend([vec![internal], external].concat(), Position::RightOf)
+        }
+        // Without an internal panel the first external output takes its place
+        (Policy::Mirror, None) => {
+            let mut external = external.into_iter();
+            mirror(external.next()?, external.collect())
+        }
+        (Policy::ExtendLeft, None) => extend(external, Position::LeftOf),
+        (_, internal) => extend(
+            internal.into_iter().chain(external).collect(),
+            Position::RightOf,
+        ),
+    }
+    .into_iter();
+
+    let (primary, state, position) = active.next()?;
+    layout.add(Output {
+        is_primary: true,
+        ..active_output(&primary, &output_modes[&primary], state, position)
+    });
+    for (output_name, state, position) in active {
+        layout.add(active_output(
+            &output_name,
+            &output_modes[&output_name],
+            state,
+            position,
+        ));
+    }
+    for output_name in turned_off {
+        layout.add(Output {
+            name: output_name,
+            ..Output::new()
+        });
+    }
+    Some(layout)
+}
+
+fn mirror(base: String, others: Vec<String>) -> Vec<(String, State, Position)> {
+    let mut outputs = vec![(base.clone(), State::Connected, Position::Center)];
+    outputs.extend(others.into_iter().map(|output_name| {
+        (
+            output_name,
+            State::Duplicated(base.clone()),
+            Position::Center,
+        )
+    }));
+    outputs
+}
+
+/// Places every output next to the previous one, the first output stays in the center
+fn extend(outputs: Vec<String>, next_to: fn(String) -> Position) -> Vec<(String, State, Position)> {
+    let mut previous: Option<String> = None;
+    outputs
+        .into_iter()
+        .map(|output_name| {
+            let position = previous
+                .replace(output_name.clone())
+                .map_or(Position::Center, next_to);
+            (output_name, State::Connected, position)
+        })
+        .collect()
+}
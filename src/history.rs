@@ -0,0 +1,160 @@
+/// Layouts applied so far, each with a snapshot of the outputs before it, so they can be undone
+use crate::{
+    backup,
+    cli::{cmd::CmdResult, xrandr::Xrandr},
+    config::{self, LayoutConfig},
+    lid::Lid,
+    paths,
+    screen::Layout,
+    state::{self, AppState},
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const HISTORY_FILE: &str = "history.toml";
+const MAX_ENTRIES: usize = 50;
+/// Name of the snapshots of live states none of the saved layouts matches
+pub const UNSAVED_LAYOUT_NAME: &str = "Unsaved";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    pub layout: String,
+    /// How the outputs were configured before the layout was applied
+    pub previous: Layout,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct History {
+    #[serde(skip_serializing, skip_deserializing)]
+    pub file: PathBuf,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+}
+
+/// Formats seconds since the Unix epoch in the local timezone, e.g. `2022-11-20 18:03:41`
+pub fn format_time(time: u64) -> String {
+    let time = time as libc::time_t;
+    // SAFETY: zeroed tm is a valid value, and localtime_r only writes to it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers are valid for the duration of the call
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return time.to_string();
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+/// Applies the saved layout of the name, if there is one, see `run`
+pub fn apply(config: &LayoutConfig, layout_name: &str, xrandr: &Xrandr) -> CmdResult<()> {
+    match config.get(layout_name) {
+        Some(layout) => run(config, layout, xrandr),
+        None => Ok(()),
+    }
+}
+
+/// Applies the saved layout chosen by hand and remembers it for the connected monitors,
+/// with the same outputs the daemon looks the choice up with
+pub fn apply_by_hand(config: &LayoutConfig, layout_name: &str, xrandr: &Xrandr) -> CmdResult<()> {
+    apply(config, layout_name, xrandr)?;
+    let setup = state::setup_key(
+        &Lid::detect().connected_outputs(xrandr, &config.settings)?,
+        &xrandr.get_edids().unwrap_or_default(),
+    );
+    state::learn(&setup, layout_name)
+        .unwrap_or_else(|error| eprintln!("Error saving the choice: {}", error));
+    Ok(())
+}
+
+/// Applies a layout, which doesn't have to be saved in the config, records it as the one
+/// applied last and in the history with a snapshot of the outputs before it
+pub fn run(config: &LayoutConfig, layout: &Layout, xrandr: &Xrandr) -> CmdResult<()> {
+    let last_applied = AppState::load().ok().and_then(|state| state.last_applied);
+    let previous = xrandr.get_live_outputs().map(|live| {
+        Layout::from_live(
+            config
+                .current_layout(&live, last_applied.as_deref())
+                .map_or(UNSAVED_LAYOUT_NAME, |current| &current.name),
+            &live,
+        )
+    });
+    config.run_layout(layout, xrandr)?;
+    state::record_applied(&layout.name)
+        .unwrap_or_else(|error| eprintln!("Error saving the applied layout: {}", error));
+    if let Ok(previous) = previous {
+        History::load()
+            .and_then(|mut history| history.record(&layout.name, previous))
+            .unwrap_or_else(|error| eprintln!("Error saving the history: {}", error));
+    }
+    Ok(())
+}
+
+/// Restores the outputs as they were before the last applied layout, and forgets that layout
+pub fn undo(config: &LayoutConfig, xrandr: &Xrandr) -> CmdResult<Option<Entry>> {
+    let mut history = History::load()?;
+    let entry = match history.entries.last() {
+        Some(entry) => entry.clone(),
+        None => return Ok(None),
+    };
+    // The snapshot isn't recorded, so undoing again goes further back
+    config.run_layout(&entry.previous, xrandr)?;
+    history.pop()?;
+    Ok(Some(entry))
+}
+
+impl History {
+    pub fn load() -> Result<Self, config::Error> {
+        let file = paths::state_dir()?.join(HISTORY_FILE);
+        let mut history = match fs::read_to_string(&file) {
+            Ok(content) => toml_edit::de::from_str::<Self>(&content)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(error.into()),
+        };
+        history.file = file;
+        Ok(history)
+    }
+
+    /// Records the applied layout, forgetting the oldest entries
+    pub fn record(&mut self, layout_name: &str, previous: Layout) -> Result<(), config::Error> {
+        self.entries.push(Entry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            layout: layout_name.to_string(),
+            previous,
+        });
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+        self.save()
+    }
+
+    /// Removes and returns the last entry, whose snapshot restores the outputs
+    pub fn pop(&mut self) -> Result<Option<Entry>, config::Error> {
+        let entry = self.entries.pop();
+        if entry.is_some() {
+            self.save()?;
+        }
+        Ok(entry)
+    }
+
+    fn save(&self) -> Result<(), config::Error> {
+        backup::write_atomic(
+            &self.file,
+            toml_edit::ser::to_string_pretty(self)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+/// Commands run before and after a layout is applied
+use crate::{
+    cli::cmd,
+    screen::{Layout, State},
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+pub const DEFAULT_TIMEOUT: u64 = 10;
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT
+}
+
+/// Hooks of a single layout
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct Hooks {
+    #[serde(default)]
+    pub pre_apply: Vec<String>,
+    #[serde(default)]
+    pub post_apply: Vec<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre_apply.is_empty() && self.post_apply.is_empty()
+    }
+}
+
+/// Hooks run for every applied layout and for the daemon's events,
+/// configured in the `[hooks]` table of config.toml
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct GlobalHooks {
+    #[serde(default)]
+    pub pre_apply: Vec<String>,
+    #[serde(default)]
+    pub post_apply: Vec<String>,
+    #[serde(default)]
+    pub on_output_connected: Vec<String>,
+    #[serde(default)]
+    pub on_output_disconnected: Vec<String>,
+    #[serde(default)]
+    pub on_no_match: Vec<String>,
+    #[serde(default)]
+    pub on_apply_failed: Vec<String>,
+    #[serde(default)]
+    pub on_rollback: Vec<String>,
+    /// Seconds after which a hook is killed
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for GlobalHooks {
+    fn default() -> Self {
+        Self {
+            pre_apply: Vec::new(),
+            post_apply: Vec::new(),
+            on_output_connected: Vec::new(),
+            on_output_disconnected: Vec::new(),
+            on_no_match: Vec::new(),
+            on_apply_failed: Vec::new(),
+            on_rollback: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl GlobalHooks {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn for_event(&self, event: Event) -> &[String] {
+        match event {
+            Event::OutputConnected => &self.on_output_connected,
+            Event::OutputDisconnected => &self.on_output_disconnected,
+            Event::NoMatch => &self.on_no_match,
+            Event::ApplyFailed => &self.on_apply_failed,
+            Event::Rollback => &self.on_rollback,
+        }
+    }
+}
+
+/// Events of the daemon scripts can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    OutputConnected,
+    OutputDisconnected,
+    NoMatch,
+    ApplyFailed,
+    Rollback,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputIdentity {
+    pub name: String,
+    /// Manufacturer, product code and serial number read from the EDID
+    pub edid: Option<String>,
+}
+
+/// Passed as JSON on stdin to the hooks of an event
+#[derive(Debug, Clone, Serialize)]
+pub struct Payload {
+    pub event: Event,
+    /// Outputs the event is about, e.g. the ones which were just connected
+    pub outputs: Vec<OutputIdentity>,
+    /// Every output connected at the moment of the event
+    pub connected: Vec<OutputIdentity>,
+    pub layout: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    PreApply,
+    PostApply,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PreApply => "pre_apply",
+            Self::PostApply => "post_apply",
+        })
+    }
+}
+
+fn env_name(output_name: &str) -> String {
+    output_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Environment variables describing the layout and its outputs, e.g.
+/// `SLAM_LAYOUT`, `SLAM_OUTPUTS`, `SLAM_PRIMARY` and `SLAM_OUTPUT_HDMI_1_MODE`
+pub fn layout_env(layout: &Layout) -> Vec<(String, String)> {
+    let mut output_names = layout.outputs.keys().cloned().collect::<Vec<String>>();
+    output_names.sort();
+    let (active, disabled): (Vec<&String>, Vec<&String>) =
+        output_names.iter().partition(|output_name| {
+            !matches!(layout.outputs[*output_name].state, State::Disconnected)
+        });
+    let join = |names: &[&String]| {
+        names
+            .iter()
+            .map(|name| name.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    };
+    let mut envs = vec![
+        ("SLAM_LAYOUT".to_string(), layout.name.clone()),
+        ("SLAM_OUTPUTS".to_string(), join(&active)),
+        ("SLAM_DISABLED_OUTPUTS".to_string(), join(&disabled)),
+        (
+            "SLAM_PRIMARY".to_string(),
+            layout
+                .outputs
+                .values()
+                .find(|output| output.is_primary)
+                .map(|output| output.name.clone())
+                .unwrap_or_default(),
+        ),
+    ];
+    for output_name in active {
+        let output = &layout.outputs[output_name];
+        let prefix = format!("SLAM_OUTPUT_{}", env_name(output_name));
+        envs.extend([
+            (
+                format!("{}_MODE", prefix),
+                output.mode.resolution.to_string(),
+            ),
+            (format!("{}_RATE", prefix), output.mode.rate.to_string()),
+            (format!("{}_STATE", prefix), output.state.to_string()),
+            (format!("{}_POSITION", prefix), output.position.to_string()),
+            (
+                format!("{}_ORIENTATION", prefix),
+                output.orientation.to_string(),
+            ),
+        ]);
+        if let Some(related_to) = output.position.related_to() {
+            envs.push((format!("{}_RELATIVE_TO", prefix), related_to.clone()));
+        }
+        if let State::Duplicated(same_as) = &output.state {
+            envs.push((format!("{}_SAME_AS", prefix), same_as.clone()));
+        }
+    }
+    envs
+}
+
+/// Runs the hooks subscribed to the event with the payload on stdin, logging the failed ones
+pub fn emit(hooks: &GlobalHooks, payload: &Payload) {
+    let commands = hooks.for_event(payload.event);
+    if commands.is_empty() {
+        return;
+    }
+    let input = serde_json::to_vec(payload).expect("Payload consists of strings only.");
+    let envs = [(
+        "SLAM_EVENT".to_string(),
+        serde_json::to_value(payload.event)
+            .ok()
+            .and_then(|event| event.as_str().map(str::to_string))
+            .unwrap_or_default(),
+    )];
+    for command in commands {
+        if let Err(error) = cmd::run_with_timeout(
+            command,
+            &envs,
+            Some(&input),
+            Duration::from_secs(hooks.timeout),
+        ) {
+            eprintln!("Hook {:?} `{}` failed: {}", payload.event, command, error);
+        }
+    }
+}
+
+/// Runs the hooks of the stage one by one, logging the failed ones
+pub fn run(stage: Stage, commands: &[String], layout: &Layout, timeout: u64) {
+    if commands.is_empty() {
+        return;
+    }
+    let mut envs = layout_env(layout);
+    envs.push(("SLAM_HOOK".to_string(), stage.to_string()));
+    for command in commands {
+        if let Err(error) =
+            cmd::run_with_timeout(command, &envs, None, Duration::from_secs(timeout))
+        {
+            eprintln!("Hook {} `{}` failed: {}", stage, command, error);
+        }
+    }
+}
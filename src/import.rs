@@ -0,0 +1,46 @@
+/// Converts the configs of other display tools into layouts
+pub mod autorandr;
+pub mod xrandr_script;
+
+use crate::screen::Orientation;
+use std::{fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The file, the number of the line and what is wrong with it
+    Invalid(PathBuf, usize, String),
+    /// The name of the imported layout and why the config can't hold it
+    InvalidLayout(String, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Failed to read the file to import: {}", error),
+            Self::Invalid(path, line, message) => {
+                write!(f, "{}:{}: {}", path.to_string_lossy(), line, message)
+            }
+            Self::InvalidLayout(layout_name, message) => {
+                write!(f, "Cannot import layout {}: {}", layout_name, message)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Value of xrandr's `--rotate`, which autorandr saves as is
+fn parse_rotation(rotation: &str) -> Option<Orientation> {
+    match rotation {
+        "normal" => Some(Orientation::Normal),
+        "inverted" => Some(Orientation::Inverted),
+        "left" => Some(Orientation::Left),
+        "right" => Some(Orientation::Right),
+        _ => None,
+    }
+}
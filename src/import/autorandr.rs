@@ -0,0 +1,227 @@
+/// Profiles of autorandr: a directory per profile with the xrandr options of its outputs
+/// in `config`, and the EDID of the monitor on every output in `setup`
+use super::{parse_rotation, Error};
+use crate::screen::{self, Layout, Output, Position, State};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_FILE: &str = "config";
+const SETUP_FILE: &str = "setup";
+/// autorandr accepts any monitor on an output with this fingerprint
+const ANY_FINGERPRINT: &str = "*";
+
+/// `$XDG_CONFIG_HOME/autorandr`, or `~/.autorandr` of older autorandr versions
+pub fn default_dir() -> PathBuf {
+    let dir = dirs::config_dir().unwrap_or_default().join("autorandr");
+    let legacy_dir = dirs::home_dir().unwrap_or_default().join(".autorandr");
+    if !dir.is_dir() && legacy_dir.is_dir() {
+        legacy_dir
+    } else {
+        dir
+    }
+}
+
+/// Converts every profile of the directory into a layout named after the profile
+pub fn import(dir: &Path) -> Result<Vec<Layout>, Error> {
+    let mut layouts = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let profile_dir = entry?.path();
+        let config_path = profile_dir.join(CONFIG_FILE);
+        // Hooks like postswitch are kept next to the profiles
+        if !config_path.is_file() {
+            continue;
+        }
+        let profile_name = profile_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut layout = parse_config(
+            &profile_name,
+            &config_path,
+            &fs::read_to_string(&config_path)?,
+        )?;
+        match fs::read_to_string(profile_dir.join(SETUP_FILE)) {
+            Ok(setup) => add_fingerprints(&mut layout, &setup),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+        layouts.push(layout);
+    }
+    layouts.sort_by(|layout, other| layout.name.cmp(&other.name));
+    Ok(layouts)
+}
+
+/// Options autorandr saves besides the supported ones, e.g. `crtc`, `gamma` or `transform`,
+/// are left to xrandr
+fn parse_config(profile_name: &str, path: &Path, content: &str) -> Result<Layout, Error> {
+    let mut layout = Layout {
+        name: profile_name.to_string(),
+        ..Layout::new()
+    };
+    let mut current: Option<Output> = None;
+    for (index, line) in content.lines().enumerate() {
+        let invalid = |message: String| Error::Invalid(path.to_path_buf(), index + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(key, value)| (key, value.trim()));
+        if key == "output" {
+            if let Some(output) = current.take() {
+                layout.add(output);
+            }
+            current = Some(Output {
+                name: value.to_string(),
+                state: State::Connected,
+                ..Output::new()
+            });
+            continue;
+        }
+        let output = current
+            .as_mut()
+            .ok_or_else(|| invalid(format!("Expected an output before {}", key)))?;
+        let parse_error = |error: screen::Error| invalid(error.to_string());
+        match key {
+            "off" => output.state = State::Disconnected,
+            "primary" => output.is_primary = true,
+            "mode" => output.mode.resolution = value.parse().map_err(parse_error)?,
+            "rate" => output.mode.rate = value.parse().map_err(parse_error)?,
+            "pos" => output.position = Position::Absolute(value.parse().map_err(parse_error)?),
+            "scale" => output.scale = Some(value.parse().map_err(parse_error)?),
+            "rotate" => {
+                output.orientation = parse_rotation(value)
+                    .ok_or_else(|| invalid(format!("Invalid rotation: {}", value)))?
+            }
+            _ => {}
+        }
+    }
+    if let Some(output) = current {
+        layout.add(output);
+    }
+    Ok(layout)
+}
+
+/// Keeps the EDID of the monitor on every output, so the layout matches only these monitors
+fn add_fingerprints(layout: &mut Layout, setup: &str) {
+    for line in setup.lines() {
+        let (output_name, fingerprint) = match line.trim().split_once(char::is_whitespace) {
+            Some((output_name, fingerprint)) => (output_name, fingerprint.trim()),
+            None => continue,
+        };
+        if let Some(output) = layout.outputs.get_mut(output_name) {
+            if fingerprint != ANY_FINGERPRINT {
+                output.edid = Some(fingerprint.to_lowercase());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        edid::Edid,
+        screen::{Orientation, Point, Rate},
+    };
+    use std::collections::HashMap;
+
+    /// Saved by `autorandr --save docked`
+    const CONFIG: &str = "\
+output DP-1
+off
+output HDMI-1
+crtc 1
+mode 2560x1440
+pos 1920x0
+rate 59.95
+x-prop-broadcast_rgb Automatic
+x-prop-non_desktop 0
+output eDP-1
+crtc 0
+mode 1920x1080
+pos 0x0
+primary
+rate 60.01
+rotate left
+scale 1.5x1.5
+";
+
+    const SETUP: &str = "\
+HDMI-1 00FFFFFFFFFFFF0010AC72A04C4C4A300F1B0103803C2278EA4455A9554D9D26
+eDP-1 *
+";
+
+    fn docked() -> Layout {
+        let mut layout = parse_config("docked", Path::new("config"), CONFIG).unwrap();
+        add_fingerprints(&mut layout, SETUP);
+        layout
+    }
+
+    #[test]
+    fn parses_outputs() {
+        let layout = docked();
+        assert_eq!(layout.name, "docked");
+        assert_eq!(layout.outputs.len(), 3);
+        assert_eq!(layout.outputs["DP-1"].state, State::Disconnected);
+
+        let hdmi = &layout.outputs["HDMI-1"];
+        assert_eq!(hdmi.state, State::Connected);
+        assert_eq!(hdmi.mode.resolution, "2560x1440".parse().unwrap());
+        assert_eq!(hdmi.mode.rate, "60".parse::<Rate>().unwrap());
+        assert_eq!(hdmi.position, Position::Absolute(Point { x: 1920, y: 0 }));
+        assert!(!hdmi.is_primary);
+
+        let panel = &layout.outputs["eDP-1"];
+        assert!(panel.is_primary);
+        assert_eq!(panel.orientation, Orientation::Left);
+        assert_eq!(panel.position, Position::Absolute(Point { x: 0, y: 0 }));
+        assert!(panel.scale.is_some());
+    }
+
+    #[test]
+    fn rejects_options_before_an_output() {
+        assert!(parse_config("broken", Path::new("config"), "mode 1920x1080\n").is_err());
+        assert!(parse_config("broken", Path::new("config"), "output eDP-1\nrotate up\n").is_err());
+    }
+
+    #[test]
+    fn keeps_fingerprints_except_wildcards() {
+        let layout = docked();
+        assert_eq!(
+            layout.outputs["HDMI-1"].edid.as_deref(),
+            Some("00ffffffffffff0010ac72a04c4c4a300f1b0103803c2278ea4455a9554d9d26")
+        );
+        assert_eq!(layout.outputs["eDP-1"].edid, None);
+        assert_eq!(layout.outputs["DP-1"].edid, None);
+    }
+
+    #[test]
+    fn matches_only_the_saved_monitors() {
+        let layout = docked();
+        let edid = |hex: &str| Edid {
+            hex: hex.to_string(),
+        };
+        let mut edids = HashMap::from([
+            (
+                "HDMI-1".to_string(),
+                edid("00ffffffffffff0010ac72a04c4c4a300f1b0103803c2278ea4455a9554d9d26"),
+            ),
+            (
+                "eDP-1".to_string(),
+                edid("00ffffffffffff0006af3d2100000000"),
+            ),
+        ]);
+        assert!(layout.matches_edids(&edids));
+        edids.insert(
+            "HDMI-1".to_string(),
+            edid("00ffffffffffff005a633a7a0f010101"),
+        );
+        assert!(!layout.matches_edids(&edids));
+        edids.remove("HDMI-1");
+        assert!(!layout.matches_edids(&edids));
+    }
+}
@@ -0,0 +1,256 @@
+/// Shell scripts running `xrandr --output ...`, like the ones arandr saves to `~/.screenlayout`
+use super::{parse_rotation, Error};
+use crate::screen::{self, Layout, Mode, Output, Position, State};
+use std::{fs, path::Path};
+
+/// Tokens ending the xrandr command on a line, e.g. in `xrandr ... && i3-msg restart`.
+/// They are also stripped from the end of tokens like `--auto;`, so `&&` comes before `&`
+const COMMAND_SEPARATORS: [&str; 5] = [";", "&&", "||", "|", "&"];
+
+/// Converts the xrandr commands of the script into a layout named after the file
+pub fn import(path: &Path) -> Result<Layout, Error> {
+    let name = path
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    parse_script(&name, path, &fs::read_to_string(path)?)
+}
+
+/// Lines continued with a backslash are joined, numbered by the line they start on
+fn join_lines(content: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut continued = false;
+    for (index, line) in content.lines().enumerate() {
+        let (line, continues) = match line.trim_end().strip_suffix('\\') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        match lines.last_mut() {
+            Some((_, previous)) if continued => {
+                previous.push(' ');
+                previous.push_str(line);
+            }
+            _ => lines.push((index + 1, line.to_string())),
+        }
+        continued = continues;
+    }
+    lines
+}
+
+fn is_xrandr(token: &str) -> bool {
+    Path::new(token)
+        .file_name()
+        .is_some_and(|name| name == "xrandr")
+}
+
+fn unquote(token: &str) -> &str {
+    token.trim_matches(|c| c == '"' || c == '\'')
+}
+
+/// Arguments of the xrandr command on the line, up to the end of the command
+fn xrandr_args(line: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    for token in line
+        .split_whitespace()
+        .skip_while(|token| !is_xrandr(unquote(token)))
+        .skip(1)
+    {
+        match COMMAND_SEPARATORS
+            .iter()
+            .find_map(|separator| token.strip_suffix(separator))
+        {
+            Some(arg) => {
+                if !arg.is_empty() {
+                    args.push(unquote(arg));
+                }
+                break;
+            }
+            None => args.push(unquote(token)),
+        }
+    }
+    args
+}
+
+/// Options of xrandr besides the supported ones, e.g. `--dpi`, `--crtc` or `--transform`,
+/// are left to xrandr
+fn parse_script(name: &str, path: &Path, content: &str) -> Result<Layout, Error> {
+    let mut layout = Layout {
+        name: name.to_string(),
+        ..Layout::new()
+    };
+    for (number, line) in join_lines(content) {
+        let invalid = |message: String| Error::Invalid(path.to_path_buf(), number, message);
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut tokens = xrandr_args(line).into_iter();
+        let mut current: Option<Output> = None;
+        while let Some(option) = tokens.next() {
+            if option == "--output" {
+                let output_name = tokens
+                    .next()
+                    .ok_or_else(|| invalid("Expected an output after --output".to_string()))?;
+                if let Some(output) = current.take() {
+                    layout.add(output);
+                }
+                current = Some(
+                    layout
+                        .outputs
+                        .remove(output_name)
+                        .unwrap_or_else(|| Output {
+                            name: output_name.to_string(),
+                            state: State::Connected,
+                            ..Output::new()
+                        }),
+                );
+                continue;
+            }
+            // Options for the whole screen, like --dpi, come before the first output
+            let Some(output) = current.as_mut() else {
+                continue;
+            };
+            let mut value = || {
+                tokens
+                    .next()
+                    .ok_or_else(|| invalid(format!("Expected a value after {}", option)))
+            };
+            let parse_error = |error: screen::Error| invalid(error.to_string());
+            match option {
+                "--auto" => output.mode = Mode::default(),
+                "--off" => output.state = State::Disconnected,
+                "--primary" => output.is_primary = true,
+                "--mode" => output.mode.resolution = value()?.parse().map_err(parse_error)?,
+                "--rate" | "--refresh" => {
+                    output.mode.rate = value()?.parse().map_err(parse_error)?
+                }
+                "--pos" => {
+                    output.position = Position::Absolute(value()?.parse().map_err(parse_error)?)
+                }
+                "--scale" => output.scale = Some(value()?.parse().map_err(parse_error)?),
+                "--rotate" => {
+                    let rotation = value()?;
+                    output.orientation = parse_rotation(rotation)
+                        .ok_or_else(|| invalid(format!("Invalid rotation: {}", rotation)))?
+                }
+                "--same-as" => output.state = State::Duplicated(value()?.to_string()),
+                "--left-of" => output.position = Position::LeftOf(value()?.to_string()),
+                "--right-of" => output.position = Position::RightOf(value()?.to_string()),
+                "--above" => output.position = Position::Above(value()?.to_string()),
+                "--below" => output.position = Position::Below(value()?.to_string()),
+                // The values of unsupported options are skipped as unknown options
+                _ => {}
+            }
+        }
+        if let Some(output) = current {
+            layout.add(output);
+        }
+    }
+    if layout.is_empty() {
+        return Err(Error::Invalid(
+            path.to_path_buf(),
+            content.lines().count().max(1),
+            "Found no xrandr command with --output".to_string(),
+        ));
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::{Orientation, Point, Rate};
+
+    /// Saved by arandr to ~/.screenlayout/docked.sh
+    const ARANDR: &str = "#!/bin/sh
+xrandr --output eDP-1 --primary --mode 1920x1080 --pos 0x0 --rotate normal --output HDMI-1 --mode 2560x1440 --pos 1920x0 --rotate left --output DP-1 --off --output DP-2 --off
+";
+
+    fn parse(content: &str) -> Result<Layout, Error> {
+        parse_script("docked", Path::new("docked.sh"), content)
+    }
+
+    #[test]
+    fn parses_arandr_scripts() {
+        let layout = parse(ARANDR).unwrap();
+        assert_eq!(layout.name, "docked");
+        assert_eq!(layout.outputs.len(), 4);
+
+        let panel = &layout.outputs["eDP-1"];
+        assert!(panel.is_primary);
+        assert_eq!(panel.state, State::Connected);
+        assert_eq!(panel.mode.resolution, "1920x1080".parse().unwrap());
+        assert_eq!(panel.position, Position::Absolute(Point { x: 0, y: 0 }));
+
+        let hdmi = &layout.outputs["HDMI-1"];
+        assert!(!hdmi.is_primary);
+        assert_eq!(hdmi.orientation, Orientation::Left);
+        assert_eq!(hdmi.position, Position::Absolute(Point { x: 1920, y: 0 }));
+
+        assert_eq!(layout.outputs["DP-1"].state, State::Disconnected);
+        assert_eq!(layout.outputs["DP-2"].state, State::Disconnected);
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        let lines = join_lines("#!/bin/sh\nxrandr \\\n  --output eDP-1 \\\n  --auto\nexit 0\n");
+        assert_eq!(
+            lines,
+            vec![
+                (1, "#!/bin/sh".to_string()),
+                (2, "xrandr    --output eDP-1    --auto".to_string()),
+                (5, "exit 0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_continued_commands() {
+        let script = "#!/bin/sh
+/usr/bin/xrandr \\
+    --output HDMI-1 --mode 1920x1080 --rate 59.94 --same-as eDP-1 \\
+    --output eDP-1 --auto --right-of DP-1
+";
+        let layout = parse(script).unwrap();
+        let hdmi = &layout.outputs["HDMI-1"];
+        assert_eq!(hdmi.state, State::Duplicated("eDP-1".to_string()));
+        assert_eq!(hdmi.mode.rate, "60".parse::<Rate>().unwrap());
+        let panel = &layout.outputs["eDP-1"];
+        assert!(panel.mode.is_auto());
+        assert_eq!(panel.position, Position::RightOf("DP-1".to_string()));
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_command() {
+        let layout = parse("xrandr --output eDP-1 --auto; xrandr --output HDMI-1 --off\n").unwrap();
+        assert_eq!(layout.outputs.len(), 1);
+        assert!(layout.outputs["eDP-1"].mode.is_auto());
+
+        let layout =
+            parse("xrandr --output eDP-1 --primary&& i3-msg --output x restart\n").unwrap();
+        assert_eq!(layout.outputs.len(), 1);
+        assert!(layout.outputs["eDP-1"].is_primary);
+    }
+
+    #[test]
+    fn skips_comments_and_other_commands() {
+        let layout =
+            parse("# xrandr --output DP-1 --off\nsleep 1\nxrandr --dpi 96 --output eDP-1 --auto\n")
+                .unwrap();
+        assert_eq!(layout.outputs.len(), 1);
+        assert!(layout.outputs.contains_key("eDP-1"));
+    }
+
+    #[test]
+    fn rejects_scripts_without_outputs() {
+        assert!(parse("#!/bin/sh\nxrandr --auto\n").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        assert!(parse("xrandr --output\n").is_err());
+        assert!(parse("xrandr --output eDP-1 --mode\n").is_err());
+        assert!(parse("xrandr --output eDP-1 --rotate sideways\n").is_err());
+    }
+}
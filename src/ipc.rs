@@ -0,0 +1,199 @@
+/// Control socket used to talk to the running daemon
+use crate::paths;
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+const RESPONSE_OK: &str = "ok";
+const RESPONSE_ERROR: &str = "error";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    NotRunning,
+    InvalidRequest(String),
+    InvalidResponse(String),
+    Daemon(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Failed to talk to the daemon: {}", error),
+            Self::NotRunning => write!(f, "The daemon is not running"),
+            Self::InvalidRequest(request) => write!(f, "Invalid request: {}", request),
+            Self::InvalidResponse(response) => {
+                write!(f, "Invalid response from the daemon: {}", response)
+            }
+            Self::Daemon(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Status,
+    Apply(String),
+    Rematch,
+    Reload,
+    Pause,
+    Resume,
+    Undo,
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Status => write!(f, "status"),
+            Self::Apply(layout_name) => write!(f, "apply {}", layout_name),
+            Self::Rematch => write!(f, "rematch"),
+            Self::Reload => write!(f, "reload"),
+            Self::Pause => write!(f, "pause"),
+            Self::Resume => write!(f, "resume"),
+            Self::Undo => write!(f, "undo"),
+        }
+    }
+}
+
+impl FromStr for Request {
+    fn from_str(request: &str) -> Result<Self, Self::Err> {
+        match request.trim().split_once(' ') {
+            Some(("apply", layout_name)) if !layout_name.trim().is_empty() => {
+                Ok(Self::Apply(layout_name.trim().to_string()))
+            }
+            None => match request.trim() {
+                "status" => Ok(Self::Status),
+                "rematch" => Ok(Self::Rematch),
+                "reload" => Ok(Self::Reload),
+                "pause" => Ok(Self::Pause),
+                "resume" => Ok(Self::Resume),
+                "undo" => Ok(Self::Undo),
+                other => Err(Error::InvalidRequest(other.to_string())),
+            },
+            _ => Err(Error::InvalidRequest(request.trim().to_string())),
+        }
+    }
+
+    type Err = Error;
+}
+
+pub type Response = Result<String, String>;
+
+/// Listening side of the control socket. Connections are read on their own threads,
+/// so a slow client can't hold up the daemon, which polls the requests between checks of the outputs
+pub struct Server {
+    requests: Receiver<(Request, Sender<Response>)>,
+    path: PathBuf,
+}
+
+impl Server {
+    pub fn bind() -> Result<Self, Error> {
+        let path = paths::socket_file()?;
+        if UnixStream::connect(&path).is_ok() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another daemon is listening on {:?}", path),
+            )));
+        }
+        // The socket is left behind by a daemon which didn't exit cleanly
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || Self::accept_connections(&listener, &sender));
+        Ok(Self { requests, path })
+    }
+
+    fn accept_connections(listener: &UnixListener, requests: &Sender<(Request, Sender<Response>)>) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let requests = requests.clone();
+                    thread::spawn(move || {
+                        if let Err(error) = Self::handle_connection(stream, &requests) {
+                            eprintln!("Error handling a control request: {}", error);
+                        }
+                    });
+                }
+                Err(error) => eprintln!("Error accepting a control connection: {}", error),
+            }
+        }
+    }
+
+    /// Handles every pending request without blocking
+    pub fn handle_requests(&self, mut handler: impl FnMut(Request) -> Response) {
+        while let Ok((request, reply)) = self.requests.try_recv() {
+            // The client may have given up waiting already
+            let _ = reply.send(handler(request));
+        }
+    }
+
+    fn handle_connection(
+        stream: UnixStream,
+        requests: &Sender<(Request, Sender<Response>)>,
+    ) -> Result<(), Error> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut line = String::new();
+        if BufReader::new(&stream).read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let response = match line.parse() {
+            Ok(request) => {
+                let (reply, response) = mpsc::channel();
+                // Fails only once the daemon has stopped, which drops the reply sender as well
+                let _ = requests.send((request, reply));
+                response
+                    .recv()
+                    .unwrap_or_else(|_| Err("The daemon has stopped".to_string()))
+            }
+            Err(error) => Err(error.to_string()),
+        };
+        let mut stream = stream;
+        match response {
+            Ok(message) => write!(stream, "{}\n{}", RESPONSE_OK, message)?,
+            Err(message) => write!(stream, "{}\n{}", RESPONSE_ERROR, message)?,
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Sends a request to the running daemon and returns its reply
+pub fn send(request: &Request) -> Result<String, Error> {
+    let mut stream =
+        UnixStream::connect(paths::socket_file()?).map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused => Error::NotRunning,
+            _ => Error::Io(error),
+        })?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    writeln!(stream, "{}", request)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    match response.split_once('\n') {
+        Some((RESPONSE_OK, message)) => Ok(message.to_string()),
+        Some((RESPONSE_ERROR, message)) => Err(Error::Daemon(message.to_string())),
+        _ => Err(Error::InvalidResponse(response)),
+    }
+}
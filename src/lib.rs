@@ -1,14 +1,36 @@
 pub mod app;
+pub mod backup;
 pub mod cli;
 pub mod config;
 pub mod daemon;
+pub mod edid;
+pub mod export;
+pub mod fallback;
+pub mod history;
+pub mod hooks;
+pub mod import;
+pub mod ipc;
+pub mod lid;
+pub mod migration;
+pub mod notifications;
+pub mod paths;
 pub mod screen;
+pub mod settings;
+pub mod sleep;
+pub mod state;
+pub mod systemd;
 pub mod ui;
+pub mod watch;
 
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use std::{env, path::PathBuf};
 
 const PATH_TO_CONFIG: &str = ".config/slam_rs/config.toml";
+const CONFIG_PATH_IN_CONFIG_HOME: &str = "slam_rs/config.toml";
+/// Overrides where the config of the user is looked up
+pub const CONFIG_PATH_ENV: &str = "SLAM_RS_CONFIG";
+/// Its layouts are read-only defaults for every user, which the config of the user can override
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/xdg/slam-rs/config.toml";
 
 #[macro_export]
 macro_rules! exit_err {
@@ -30,32 +52,200 @@ macro_rules! vec_from_enum {
     }};
 }
 
+/// Returns `$SLAM_RS_CONFIG`, or the first existing of `$XDG_CONFIG_HOME/slam_rs/config.toml`
+/// and `~/.config/slam_rs/config.toml`, where a new config is created otherwise.
+/// Without a config home and a home dir, only the system config is left
 pub fn find_config_path() -> PathBuf {
-    dirs::home_dir()
-        .unwrap_or_else(|| exit_err!("Cannot find home dir"))
-        .join(PATH_TO_CONFIG)
+    if let Some(path) = env::var_os(CONFIG_PATH_ENV).filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    let candidates = [
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            // Relative paths are invalid according to the XDG Base Directory Specification
+            .filter(|config_home| config_home.is_absolute())
+            .map(|config_home| config_home.join(CONFIG_PATH_IN_CONFIG_HOME)),
+        dirs::home_dir().map(|home_dir| home_dir.join(PATH_TO_CONFIG)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<PathBuf>>();
+    candidates
+        .iter()
+        .find(|path| path.exists())
+        .or_else(|| candidates.first())
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(SYSTEM_CONFIG_PATH))
 }
 
 // TODO: add validation of config and layout paths via clap(validator = ...)
-// TODO: add daemon save delay
 // and add forbid_empty_values = true
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    // Path to config.toml file
+    /// Path to config.toml file
     #[arg(short, long, value_name = "FILE", value_hint = clap::ValueHint::FilePath, required = false)]
     pub config: Option<PathBuf>,
 
-    // TODO: rewrite as layout name (String)
-    // Apply layout in /path/to/layout.toml file
-    #[arg(short, long, value_name = "FILE", value_hint = clap::ValueHint::FilePath, exclusive = true, required = false)]
-    pub layout: Option<PathBuf>,
+    /// Apply layout with the given name (through the daemon if it is running)
+    #[arg(short, long, value_name = "NAME", conflicts_with_all = ["daemon", "dmenu"], required = false)]
+    pub layout: Option<String>,
 
-    // Run the daemon to auto-detect layout
-    #[arg(short, long, exclusive = true, required = false)]
+    /// Run the daemon to auto-detect layout
+    #[arg(short, long, conflicts_with_all = ["config", "dmenu"], required = false)]
     pub daemon: bool,
 
-    // Path to dmenu executable
+    /// Path to dmenu executable
     #[arg(short = 'e', value_name = "BIN", value_hint = clap::ValueHint::ExecutablePath, required = false)]
     pub dmenu: Option<PathBuf>,
+
+    /// Seconds to confirm a layout applied from the menu before it is reverted, 0 doesn't ask
+    #[arg(long, value_name = "SECS", conflicts_with = "daemon", required = false)]
+    pub rollback_timeout: Option<u64>,
+
+    #[command(flatten)]
+    pub daemon_args: DaemonArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Control the running daemon
+    #[command(subcommand)]
+    Daemon(DaemonCommand),
+    /// Restore the outputs as they were before the last applied layout
+    Undo,
+    /// List the applied layouts, the last one first
+    History,
+    /// Manage config.toml
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Add the layouts of another display tool to config.toml
+    #[command(subcommand)]
+    Import(ImportCommand),
+    /// Print a layout in a format used without slam-rs, e.g. by the login screen
+    Export {
+        layout: String,
+        #[arg(long, value_enum, default_value_t)]
+        format: export::Format,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImportCommand {
+    /// Import the profiles of autorandr, from ~/.config/autorandr by default
+    Autorandr {
+        #[arg(value_hint = clap::ValueHint::DirPath)]
+        dir: Option<PathBuf>,
+    },
+    /// Import shell scripts running xrandr, like the ones arandr saves to ~/.screenlayout
+    XrandrScript {
+        #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// List the backups made before every change of config.toml, the newest first
+    Backups,
+    /// Replace config.toml with a backup, the newest one by default
+    Restore { backup: Option<String> },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DaemonCommand {
+    /// Show whether the daemon is paused, the connected outputs and the matching and applied layouts
+    Status,
+    /// Apply layout with the given name
+    Apply { layout: String },
+    /// Select a layout for the connected outputs again
+    Rematch,
+    /// Reload config.toml
+    Reload,
+    /// Stop applying layouts automatically
+    Pause,
+    /// Continue applying layouts automatically
+    Resume,
+    /// Write a unit file to run the daemon as a `systemd --user` service
+    Install,
+}
+
+/// Options which aren't given fall back to the `[settings.daemon]` table of config.toml
+#[derive(clap::Args, Debug, Clone)]
+#[command(next_help_heading = "Daemon options")]
+pub struct DaemonArgs {
+    /// Seconds the set of connected outputs should stay unchanged before a layout is selected [default: 3]
+    #[arg(long, value_name = "SECS", requires = "daemon")]
+    pub settle: Option<u64>,
+
+    /// Interval in milliseconds between two checks of the connected outputs [default: 500]
+    #[arg(long, value_name = "MILLIS", requires = "daemon")]
+    pub poll_interval: Option<u64>,
+
+    /// Maximum number of layouts applied by the daemon per minute [default: 4]
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        requires = "daemon"
+    )]
+    pub max_applies: Option<usize>,
+
+    /// Stay in the foreground and log to stdout/stderr (e.g. as a systemd service)
+    #[arg(long, requires = "daemon")]
+    pub foreground: bool,
+
+    /// What to do when none of the saved layouts matches the connected outputs [default: mirror]
+    #[arg(long, value_name = "POLICY", value_enum, requires = "daemon")]
+    pub fallback: Option<fallback::Policy>,
+
+    /// Save layouts generated by the fallback policy to config.toml [default: true]
+    #[arg(long, overrides_with = "no_save_fallback", requires = "daemon")]
+    save_fallback: bool,
+
+    /// Don't save layouts generated by the fallback policy to config.toml
+    #[arg(long, overrides_with = "save_fallback", requires = "daemon")]
+    no_save_fallback: bool,
+
+    /// Open the menu to ask what to do when none of the saved layouts matches [default: false]
+    #[arg(long, overrides_with = "no_ask", requires = "daemon")]
+    ask: bool,
+
+    /// Don't open the menu, use the fallback policy instead
+    #[arg(long, overrides_with = "ask", requires = "daemon")]
+    no_ask: bool,
+
+    /// Show a desktop notification whenever a layout is applied [default: false]
+    #[arg(long, overrides_with = "no_notify", requires = "daemon")]
+    notify: bool,
+
+    /// Don't show desktop notifications
+    #[arg(long, overrides_with = "notify", requires = "daemon")]
+    no_notify: bool,
+}
+
+/// The value of a pair of `--x`/`--no-x` flags, the last one given wins
+fn flag(enabled: bool, disabled: bool) -> Option<bool> {
+    match (enabled, disabled) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+impl DaemonArgs {
+    pub fn save_fallback(&self) -> Option<bool> {
+        flag(self.save_fallback, self.no_save_fallback)
+    }
+
+    pub fn ask(&self) -> Option<bool> {
+        flag(self.ask, self.no_ask)
+    }
+
+    pub fn notify(&self) -> Option<bool> {
+        flag(self.notify, self.no_notify)
+    }
 }
@@ -0,0 +1,82 @@
+/// State of the laptop lid, read from ACPI or from logind's `LidClosed` property
+use crate::{
+    cli::{cmd, xrandr::Xrandr},
+    settings::Settings,
+};
+use std::fs;
+
+const ACPI_LID_DIR: &str = "/proc/acpi/button/lid";
+
+/// Returns Some(true) if any lid ACPI reports is closed, None if it reports no lid
+fn acpi_lid_closed() -> Option<bool> {
+    let mut states = fs::read_dir(ACPI_LID_DIR)
+        .ok()?
+        .filter_map(|entry| fs::read_to_string(entry.ok()?.path().join("state")).ok())
+        .peekable();
+    states.peek()?;
+    // The file looks like `state:      closed`
+    Some(states.any(|state| state.split_whitespace().nth(1) == Some("closed")))
+}
+
+/// Reads a boolean property of logind's manager, None without logind
+fn logind_property(property: &str) -> Option<bool> {
+    let output = cmd::run_and_fetch_output(&format!(
+        "busctl get-property org.freedesktop.login1 /org/freedesktop/login1 \
+         org.freedesktop.login1.Manager {} 2>/dev/null",
+        property
+    ))
+    .ok()?;
+    // The output looks like `b true`
+    match output.strip_prefix("b ")? {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lid {
+    Acpi,
+    Logind,
+    /// Desktops and machines whose lid can't be read are treated as open
+    Absent,
+}
+
+impl Lid {
+    /// Looks up once where the lid state can be read, so machines without a lid
+    /// don't run busctl on every check
+    pub fn detect() -> Self {
+        let has_acpi_lid = acpi_lid_closed().is_some();
+        match logind_property("LidPresent") {
+            Some(false) => Self::Absent,
+            // ACPI is read without running a command
+            _ if has_acpi_lid => Self::Acpi,
+            Some(true) => Self::Logind,
+            None => Self::Absent,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        match self {
+            Self::Acpi => acpi_lid_closed(),
+            Self::Logind => logind_property("LidClosed"),
+            Self::Absent => None,
+        }
+        .unwrap_or(false)
+    }
+
+    /// Connected outputs without the internal panel while the lid is closed,
+    /// so opening or closing the lid selects a layout again like a hotplug does
+    pub fn connected_outputs(
+        &self,
+        xrandr: &Xrandr,
+        settings: &Settings,
+    ) -> cmd::CmdResult<Vec<String>> {
+        let mut connected = xrandr.list_connected_outputs()?;
+        if self.is_closed() {
+            connected.retain(|output_name| !settings.is_internal_output(output_name));
+        }
+        connected.sort();
+        Ok(connected)
+    }
+}
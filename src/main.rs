@@ -1,28 +1,72 @@
 use clap::Parser;
-use slam_rs::{app, cli, daemon, exit_err, Args};
+use slam_rs::{app, daemon, exit_err, Args, Command};
 use std::process;
 
+fn exit_on_error(error: app::Error) -> ! {
+    match error {
+        app::Error::ScreenError(error) => {
+            exit_err!("Failed to read screen properties: {}", error)
+        }
+        app::Error::ConfigError(error) => exit_err!("{}", error),
+        app::Error::CmdError(error) => exit_err!("{}", error),
+        app::Error::IpcError(error) => exit_err!("{}", error),
+        app::Error::IoError(error) => exit_err!("{}", error),
+        app::Error::ImportError(error) => exit_err!("{}", error),
+        app::Error::UnknownLayout(layout_name) => exit_err!("Unknown layout: {}", layout_name),
+        app::Error::UnknownBackup(backup) if backup.is_empty() => exit_err!("No backups found"),
+        app::Error::UnknownBackup(backup) => exit_err!("Unknown backup: {}", backup),
+        app::Error::InternalError => exit_err!("Unexpected error occured!"),
+    }
+}
+
 fn main() {
     let args = Args::parse();
 
-    if args.daemon {
-        daemon::run_daemon().unwrap_or_else(|error| exit_err!("Error running slamd: {}", error));
+    if let Some(Command::Daemon(command)) = &args.command {
+        app::run_daemon_command(command).unwrap_or_else(|error| exit_on_error(error));
         process::exit(0);
     }
 
-    if let Some(layout_path) = args.layout {
-        app::apply_layout(&layout_path);
+    if args.daemon {
+        daemon::run_daemon(args.daemon_args)
+            .unwrap_or_else(|error| exit_err!("Error running slamd: {}", error));
         process::exit(0);
     }
 
     let config_path = args.config.unwrap_or_else(slam_rs::find_config_path);
 
-    app::run(&config_path, args.dmenu).unwrap_or_else(|error| match error {
-        app::Error::ScreenError(error) => {
-            exit_err!("Failed to read screen properties: {}", error)
+    match &args.command {
+        Some(Command::Undo) => {
+            app::undo(&config_path).unwrap_or_else(|error| exit_on_error(error));
+            process::exit(0);
         }
-        app::Error::ConfigError(error) => exit_err!("{}", error),
-        app::Error::CmdError(error) => exit_err!("{}", error),
-        app::Error::InternalError => exit_err!("Unexpected error occured!"),
-    })
+        Some(Command::History) => {
+            app::show_history().unwrap_or_else(|error| exit_on_error(error));
+            process::exit(0);
+        }
+        Some(Command::Config(command)) => {
+            app::run_config_command(&config_path, command)
+                .unwrap_or_else(|error| exit_on_error(error));
+            process::exit(0);
+        }
+        Some(Command::Import(command)) => {
+            app::run_import_command(&config_path, command)
+                .unwrap_or_else(|error| exit_on_error(error));
+            process::exit(0);
+        }
+        Some(Command::Export { layout, format }) => {
+            app::export_layout(&config_path, layout, *format)
+                .unwrap_or_else(|error| exit_on_error(error));
+            process::exit(0);
+        }
+        _ => {}
+    }
+
+    if let Some(layout_name) = args.layout {
+        app::apply_layout(&config_path, &layout_name).unwrap_or_else(|error| exit_on_error(error));
+        process::exit(0);
+    }
+
+    app::run(&config_path, args.dmenu, args.rollback_timeout)
+        .unwrap_or_else(|error| exit_on_error(error))
 }
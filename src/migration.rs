@@ -0,0 +1,106 @@
+/// Upgrades configs written by older versions of slam-rs to the current schema
+use crate::config::Error;
+use toml_edit::{value, DocumentMut, Item};
+
+/// Version of the config schema this slam-rs reads and writes
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Migrations indexed by the version they upgrade from
+const MIGRATIONS: [fn(&mut DocumentMut); CONFIG_VERSION as usize] = [remove_is_current];
+
+/// Version 0 stored which layout was current, it is detected from the outputs since
+fn remove_is_current(document: &mut DocumentMut) {
+    if let Some(layouts) = document
+        .get_mut("layouts")
+        .and_then(Item::as_table_like_mut)
+    {
+        for (_, layout) in layouts.iter_mut() {
+            if let Some(layout) = layout.as_table_like_mut() {
+                layout.remove("is_current");
+            }
+        }
+    }
+}
+
+/// Configs written before versioning have no version and are treated as version 0
+fn version(document: &DocumentMut) -> Result<u32, Error> {
+    match document.get("version") {
+        None => Ok(0),
+        Some(item) => item
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| Error::Invalid("version must be a positive integer".to_string())),
+    }
+}
+
+/// Upgrades the config to the current version, returns false if it already was
+pub fn migrate(document: &mut DocumentMut) -> Result<bool, Error> {
+    let version = version(document)?;
+    if version > CONFIG_VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    if version == CONFIG_VERSION {
+        return Ok(false);
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(document);
+    }
+    document["version"] = value(i64::from(CONFIG_VERSION));
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNVERSIONED: &str = r#"[layouts.Work]
+name = "Work"
+is_current = true # set by slam-rs
+
+[layouts.Work.outputs]
+"#;
+
+    fn parse(toml: &str) -> DocumentMut {
+        toml.parse().unwrap()
+    }
+
+    #[test]
+    fn migrates_unversioned_configs() {
+        let mut document = parse(UNVERSIONED);
+        assert!(migrate(&mut document).unwrap());
+        assert_eq!(
+            document.get("version").and_then(Item::as_integer),
+            Some(i64::from(CONFIG_VERSION))
+        );
+        assert!(document["layouts"]["Work"].get("is_current").is_none());
+        assert_eq!(document["layouts"]["Work"]["name"].as_str(), Some("Work"));
+    }
+
+    #[test]
+    fn keeps_current_configs() {
+        let toml = format!("version = {}\n{}", CONFIG_VERSION, UNVERSIONED);
+        let mut document = parse(&toml);
+        assert!(!migrate(&mut document).unwrap());
+        assert_eq!(document.to_string(), toml);
+    }
+
+    #[test]
+    fn rejects_newer_configs() {
+        let mut document = parse(&format!("version = {}\n", CONFIG_VERSION + 1));
+        assert!(matches!(
+            migrate(&mut document),
+            Err(Error::UnsupportedVersion(version)) if version == CONFIG_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        for toml in ["version = \"1\"\n", "version = -1\n", "version = 1.5\n"] {
+            assert!(
+                matches!(migrate(&mut parse(toml)), Err(Error::Invalid(_))),
+                "{}",
+                toml
+            );
+        }
+    }
+}
@@ -0,0 +1,127 @@
+/// Desktop notifications sent through the org.freedesktop.Notifications D-Bus API with gdbus
+use crate::cli::cmd::{CmdResult, Error};
+use regex::Regex;
+use std::{
+    env,
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+const DEST: &str = "org.freedesktop.Notifications";
+const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+const APP_NAME: &str = "slam-rs";
+const ICON: &str = "video-display";
+const EXPIRE_TIMEOUT: i32 = 5000;
+/// Key of the action button, unique enough to tell our notifications apart
+/// from the ones of other applications when the signal is received
+const MENU_ACTION: &str = "slam-rs-menu";
+
+/// Quotes the string as a GVariant text literal, which gdbus parses its arguments as
+fn gvariant_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+pub struct Notifier {
+    notifications: Sender<(String, String)>,
+}
+
+impl Notifier {
+    /// Starts sending the notifications from a thread, so a slow notification daemon doesn't
+    /// hold up the caller, and listening for clicks on the action button, which open the menu
+    pub fn start() -> Self {
+        thread::spawn(|| {
+            if let Err(error) = listen_for_actions() {
+                eprintln!("Error listening for notification actions: {}", error);
+            }
+        });
+        let (notifications, received) = mpsc::channel::<(String, String)>();
+        thread::spawn(move || {
+            // Id of the last notification, replaced by the next one so they don't pile up
+            let mut last_id = 0;
+            for (summary, body) in received {
+                match notify(last_id, &summary, &body) {
+                    Ok(id) => last_id = id,
+                    Err(error) => eprintln!("Error sending a notification: {}", error),
+                }
+            }
+        });
+        Self { notifications }
+    }
+
+    pub fn send(&self, summary: &str, body: &str) {
+        // The thread only stops when the notifier is dropped
+        let _ = self
+            .notifications
+            .send((summary.to_string(), body.to_string()));
+    }
+}
+
+/// Shows the notification in place of the one with `replaces_id` and returns its id
+fn notify(replaces_id: u32, summary: &str, body: &str) -> CmdResult<u32> {
+    let output = Command::new("gdbus")
+        .args([
+            "call",
+            "--session",
+            "--dest",
+            DEST,
+            "--object-path",
+            OBJECT_PATH,
+        ])
+        .args(["--method", &format!("{}.Notify", DEST)])
+        .arg(gvariant_string(APP_NAME))
+        .arg(format!("uint32 {}", replaces_id))
+        .arg(gvariant_string(ICON))
+        .arg(gvariant_string(summary))
+        .arg(gvariant_string(body))
+        .arg(format!(
+            "[{}, 'Choose layout']",
+            gvariant_string(MENU_ACTION)
+        ))
+        .arg("@a{sv} {}")
+        .arg(EXPIRE_TIMEOUT.to_string())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Failed(output.status));
+    }
+    // The reply looks like `(uint32 42,)`
+    std::str::from_utf8(&output.stdout)?
+        .trim()
+        .trim_start_matches("(uint32 ")
+        .trim_end_matches(",)")
+        .parse()
+        .map_err(|_| Error::InvalidOutput)
+}
+
+/// Opens the layout menu in a separate process whenever the action button is clicked,
+/// so applying a layout from it goes through the daemon as usual
+fn listen_for_actions() -> CmdResult<()> {
+    let action_regexp = Regex::new(&format!(
+        r"{}\.ActionInvoked \(uint32 \d+, '{}'\)",
+        regex::escape(DEST),
+        MENU_ACTION
+    ))
+    .expect("Hardcoded regexp.");
+    let mut monitor = Command::new("gdbus")
+        .args([
+            "monitor",
+            "--session",
+            "--dest",
+            DEST,
+            "--object-path",
+            OBJECT_PATH,
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let stdout = monitor.stdout.take().ok_or(Error::EmptyOutput)?;
+    for line in BufReader::new(stdout).lines() {
+        if action_regexp.is_match(&line?) {
+            let mut menu = Command::new(env::current_exe()?).spawn()?;
+            thread::spawn(move || menu.wait());
+        }
+    }
+    Err(Error::Failed(monitor.wait()?))
+}
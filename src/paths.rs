@@ -0,0 +1,130 @@
+/// Locations of the files slam-rs keeps besides its config:
+/// runtime files live in `$XDG_RUNTIME_DIR/slam-rs/`, persistent state in `$XDG_STATE_HOME/slam-rs/`
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::unix::{
+        fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+        io::AsRawFd,
+    },
+    path::{Path, PathBuf},
+};
+
+const APP_DIR: &str = "slam-rs";
+const PID_FILE: &str = "slamd.pid";
+const SOCKET_FILE: &str = "slamd.sock";
+const STDOUT_FILE: &str = "slamd.out";
+const STDERR_FILE: &str = "slamd.err";
+
+fn uid() -> u32 {
+    // SAFETY: getuid() is always successful and has no side effects
+    unsafe { libc::getuid() }
+}
+
+/// Creates a directory only the current user can access,
+/// and refuses to use an existing one which others could tamper with
+fn create_private_dir(path: &Path) -> io::Result<PathBuf> {
+    match fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path)
+    {
+        Ok(_) => {}
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(error) => return Err(error),
+    }
+    let metadata = fs::metadata(path)?;
+    if metadata.uid() != uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{:?} is owned by another user", path),
+        ));
+    }
+    if metadata.permissions().mode() & 0o077 != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(path.to_path_buf())
+}
+
+pub fn runtime_dir() -> io::Result<PathBuf> {
+    create_private_dir(
+        &dirs::runtime_dir()
+            .map(|runtime_dir| runtime_dir.join(APP_DIR))
+            .unwrap_or_else(|| env::temp_dir().join(format!("{}-{}", APP_DIR, uid()))),
+    )
+}
+
+pub fn state_dir() -> io::Result<PathBuf> {
+    create_private_dir(
+        &dirs::state_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Cannot find state dir"))?
+            .join(APP_DIR),
+    )
+}
+
+pub fn pid_file() -> io::Result<PathBuf> {
+    Ok(runtime_dir()?.join(PID_FILE))
+}
+
+pub fn socket_file() -> io::Result<PathBuf> {
+    Ok(runtime_dir()?.join(SOCKET_FILE))
+}
+
+pub fn stdout_file() -> io::Result<PathBuf> {
+    Ok(state_dir()?.join(STDOUT_FILE))
+}
+
+pub fn stderr_file() -> io::Result<PathBuf> {
+    Ok(state_dir()?.join(STDERR_FILE))
+}
+
+/// Pid file locked for the whole life of the daemon, so only one daemon runs per user.
+/// The lock is released by the kernel when the daemon dies, which makes pid files
+/// left by crashed daemons harmless: they are simply locked and overwritten
+pub struct PidFile {
+    file: File,
+}
+
+impl PidFile {
+    pub fn lock() -> io::Result<Self> {
+        let path = pid_file()?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(&path)?;
+        // SAFETY: the descriptor belongs to the file opened above
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::WouldBlock {
+                return Err(error);
+            }
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("the daemon is already running with pid {}", pid.trim()),
+            ));
+        }
+        let mut stale_pid = String::new();
+        file.read_to_string(&mut stale_pid)?;
+        if !stale_pid.trim().is_empty() {
+            eprintln!(
+                "Replacing stale pid file left by process {}",
+                stale_pid.trim()
+            );
+        }
+        Ok(Self { file })
+    }
+
+    /// Writes the pid of the current process, which changes after the daemon forks
+    pub fn write_pid(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{}", std::process::id())?;
+        self.file.sync_all()
+    }
+}
@@ -1,4 +1,4 @@
-use crate::exit_err;
+use crate::{edid::Edid, exit_err, hooks::Hooks};
 use itertools::Itertools;
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
@@ -19,6 +19,7 @@ pub enum Error {
     InvalidResolution(String),
     InvalidRate(String),
     InvalidPosition(String),
+    InvalidScale(String),
 }
 
 impl fmt::Display for Error {
@@ -27,6 +28,7 @@ impl fmt::Display for Error {
             Self::InvalidPosition(position) => write!(f, "Invalid position: {}", position),
             Self::InvalidResolution(resolution) => write!(f, "Invalid resolution: {}", resolution),
             Self::InvalidRate(rate) => write!(f, "Invalid refresh rate: {}", rate),
+            Self::InvalidScale(scale) => write!(f, "Invalid scale: {}", scale),
         }
     }
 }
@@ -82,9 +84,9 @@ impl FromStr for Resolution {
     type Err = Error;
 }
 
-impl ToString for Resolution {
-    fn to_string(&self) -> String {
-        format!("{}x{}", self.height, self.width)
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.height, self.width)
     }
 }
 
@@ -128,13 +130,37 @@ impl FromStr for Rate {
     type Err = Error;
 }
 
-impl ToString for Rate {
-    fn to_string(&self) -> String {
-        format!("{}", self.value)
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default, EnumIter)]
+/// Coordinates of the top left corner of an output on the screen
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl FromStr for Point {
+    /// Parses xrandr's `XxY`, e.g. `1920x0`
+    fn from_str(point: &str) -> Result<Self, Self::Err> {
+        point
+            .split_once('x')
+            .and_then(|(x, y)| {
+                Some(Self {
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| Self::Err::InvalidPosition(point.to_string()))
+    }
+
+    type Err = Error;
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq, EnumIter)]
 #[serde(tag = "type", content = "related_to")]
 pub enum Position {
     #[default]
@@ -143,6 +169,9 @@ pub enum Position {
     RightOf(String),
     Above(String),
     Below(String),
+    /// Used by snapshots of the live state, which can't be chosen from the menu
+    #[strum(disabled)]
+    Absolute(Point),
 }
 
 impl Position {
@@ -150,6 +179,16 @@ impl Position {
         Self::iter().map(|s| s.to_string()).collect()
     }
 
+    pub fn related_to(&self) -> Option<&String> {
+        match self {
+            Self::Center | Self::Absolute(_) => None,
+            Self::LeftOf(output)
+            | Self::RightOf(output)
+            | Self::Above(output)
+            | Self::Below(output) => Some(output),
+        }
+    }
+
     pub fn from(position: &str, relative_screen: Option<String>) -> Self {
         match position {
             "Center" => Self::Center,
@@ -167,16 +206,16 @@ impl Position {
     }
 }
 
-impl ToString for Position {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
             Self::Center => "Center",
             Self::LeftOf(_) => "Left of",
             Self::RightOf(_) => "Right of",
             Self::Below(_) => "Below",
             Self::Above(_) => "Above",
-        }
-        .to_string()
+            Self::Absolute(_) => "Absolute",
+        })
     }
 }
 
@@ -188,11 +227,12 @@ impl ToXrandrArg for Position {
             Self::RightOf(output) => format!("--right-of {}", output),
             Self::Below(output) => format!("--below {}", output),
             Self::Above(output) => format!("--above {}", output),
+            Self::Absolute(point) => format!("--pos {}x{}", point.x, point.y),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default, EnumIter)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq, EnumIter)]
 #[serde(tag = "type", content = "related_to")]
 pub enum State {
     #[default]
@@ -218,14 +258,13 @@ impl State {
     }
 }
 
-impl ToString for State {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
             Self::Connected => "Connected",
             Self::Duplicated(_) => "Duplicated",
             Self::Disconnected => "Disconnected",
-        }
-        .to_string()
+        })
     }
 }
 
@@ -239,7 +278,7 @@ impl ToXrandrArg for State {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default, EnumIter)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq, EnumIter)]
 #[serde(tag = "type")]
 pub enum Orientation {
     #[default]
@@ -255,15 +294,14 @@ impl Orientation {
     }
 }
 
-impl ToString for Orientation {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
             Self::Normal => "Normal",
             Self::Inverted => "Inverted",
             Self::Left => "Left",
             Self::Right => "Right",
-        }
-        .to_string()
+        })
     }
 }
 
@@ -285,23 +323,69 @@ impl From<String> for Orientation {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
 pub struct Mode {
     pub resolution: Resolution,
     pub rate: Rate,
 }
 
+impl Mode {
+    /// Modes without a resolution let xrandr pick the preferred one, as `--auto` does
+    pub fn is_auto(&self) -> bool {
+        self.resolution == Resolution::default()
+    }
+
+    /// Whether the output configured with `other` runs this mode, an unset rate matches any
+    pub fn matches(&self, other: &Self) -> bool {
+        self.is_auto()
+            || (self.resolution == other.resolution
+                && (self.rate == Rate::default() || self.rate == other.rate))
+    }
+}
+
 impl ToXrandrArg for Mode {
     fn to_xrandr_arg(&self) -> String {
-        format!(
-            "--mode {} --rate {}",
-            self.resolution.to_string(),
-            self.rate.to_string()
-        )
+        if self.is_auto() {
+            "--auto".to_string()
+        } else if self.rate == Rate::default() {
+            format!("--mode {}", self.resolution)
+        } else {
+            format!("--mode {} --rate {}", self.resolution, self.rate)
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+/// Factors the output is scaled by, e.g. `2x2` for a HiDPI panel next to a regular monitor
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct Scale {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl FromStr for Scale {
+    fn from_str(scale: &str) -> Result<Self, Self::Err> {
+        scale
+            .split_once('x')
+            .and_then(|(x, y)| {
+                Some(Self {
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                })
+            })
+            .filter(|scale| scale.x > 0.0 && scale.y > 0.0)
+            .ok_or_else(|| Self::Err::InvalidScale(scale.to_string()))
+    }
+
+    type Err = Error;
+}
+
+impl ToXrandrArg for Scale {
+    fn to_xrandr_arg(&self) -> String {
+        format!("--scale {}x{}", self.x, self.y)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
 pub struct Output {
     pub name: String,
     pub mode: Mode,
@@ -309,6 +393,11 @@ pub struct Output {
     pub state: State,
     pub position: Position,
     pub orientation: Orientation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<Scale>,
+    /// EDID of the monitor the layout was made for as hex, which the connected one has to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edid: Option<String>,
 }
 
 impl Output {
@@ -326,14 +415,23 @@ impl Output {
             state: State::Disconnected,
             position: Position::Center,
             orientation: Orientation::Normal,
+            scale: None,
+            edid: None,
         }
     }
 
     fn to_xrandr_output(&self) -> String {
         format!(
-            "--output {} {} {} {} {} {}",
+            "--output {} {} {} {} {} {}{}",
             self.name,
-            self.mode.to_xrandr_arg(),
+            {
+                // The mode of an output turned off is irrelevant
+                if matches!(self.state, State::Disconnected) {
+                    String::new()
+                } else {
+                    self.mode.to_xrandr_arg()
+                }
+            },
             self.orientation.to_xrandr_arg(),
             self.position.to_xrandr_arg(),
             self.state.to_xrandr_arg(),
@@ -343,24 +441,28 @@ impl Output {
                 } else {
                     ""
                 }
-            }
+            },
+            self.scale
+                .map(|scale| format!(" {}", scale.to_xrandr_arg()))
+                .unwrap_or_default()
         )
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
 pub struct Layout {
     pub name: String,
-    pub is_current: bool,
     pub outputs: Outputs,
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
 }
 
 impl Layout {
     pub fn new() -> Self {
         Self {
             name: String::new(),
-            is_current: false,
             outputs: Outputs::new(),
+            hooks: Hooks::default(),
         }
     }
 
@@ -376,15 +478,183 @@ impl Layout {
         self.outputs.get(output_name)
     }
 
+    /// Checks what deserializing doesn't, e.g. that outputs are placed next to outputs it turns on
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .outputs
+            .values()
+            .filter(|output| output.is_primary)
+            .count()
+            > 1
+        {
+            return Err("more than one output is primary".to_string());
+        }
+        for (output_name, output) in &self.outputs {
+            if output.name != *output_name {
+                return Err(format!("output {} is named {}", output_name, output.name));
+            }
+            let related_to = match &output.state {
+                State::Disconnected => continue,
+                State::Duplicated(same_as) => Some(same_as),
+                State::Connected => output.position.related_to(),
+            };
+            if let Some(related_to) = related_to {
+                if !self
+                    .outputs
+                    .get(related_to)
+                    .is_some_and(|related| !matches!(related.state, State::Disconnected))
+                {
+                    return Err(format!(
+                        "output {} is placed relative to {}, which the layout doesn't turn on",
+                        output_name, related_to
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the monitors connected to the outputs it turns on are the ones it was saved for,
+    /// outputs saved without an EDID accept any monitor
+    pub fn matches_edids(&self, edids: &HashMap<String, Edid>) -> bool {
+        self.outputs
+            .values()
+            .filter(|output| !matches!(output.state, State::Disconnected))
+            .all(|output| match &output.edid {
+                Some(fingerprint) => edids
+                    .get(&output.name)
+                    .is_some_and(|edid| edid.matches(fingerprint)),
+                None => true,
+            })
+    }
+
+    /// Whether the live outputs are configured the way this layout configures them.
+    /// Outputs placed in the center keep their position, so it isn't compared
+    pub fn matches_live(&self, live: &[LiveOutput]) -> bool {
+        let geometry = |output_name: &str| {
+            live.iter()
+                .find(|live_output| live_output.name == output_name)
+                .and_then(|live_output| live_output.geometry)
+        };
+        let is_placed = |output: &Output, actual: Geometry| {
+            let related = match (&output.state, output.position.related_to()) {
+                (State::Duplicated(same_as), _) => {
+                    return geometry(same_as)
+                        .is_some_and(|other| (other.x, other.y) == (actual.x, actual.y))
+                }
+                (_, Some(related_to)) => match geometry(related_to) {
+                    Some(related) => related,
+                    None => return false,
+                },
+                (_, None) => {
+                    return match output.position {
+                        Position::Absolute(point) => (point.x, point.y) == (actual.x, actual.y),
+                        _ => true,
+                    }
+                }
+            };
+            match output.position {
+                Position::LeftOf(_) => {
+                    actual.x + actual.width as i32 == related.x && actual.y == related.y
+                }
+                Position::RightOf(_) => {
+                    actual.x == related.x + related.width as i32 && actual.y == related.y
+                }
+                Position::Above(_) => {
+                    actual.y + actual.height as i32 == related.y && actual.x == related.x
+                }
+                Position::Below(_) => {
+                    actual.y == related.y + related.height as i32 && actual.x == related.x
+                }
+                Position::Center | Position::Absolute(_) => true,
+            }
+        };
+        let outputs_match = live.iter().all(|live_output| {
+            match (self.get(&live_output.name), live_output.geometry) {
+                // Outputs the layout doesn't know about should be off
+                (None, geometry) => geometry.is_none(),
+                (Some(output), None) => matches!(output.state, State::Disconnected),
+                (Some(output), Some(actual)) => {
+                    !matches!(output.state, State::Disconnected)
+                        && live_output
+                            .mode
+                            .as_ref()
+                            .is_some_and(|mode| output.mode.matches(mode))
+                        && live_output.orientation == output.orientation
+                        && (!output.is_primary || live_output.is_primary)
+                        && is_placed(output, actual)
+                }
+            }
+        });
+        // Every output the layout turns on should be connected
+        outputs_match
+            && self.outputs.values().all(|output| {
+                matches!(output.state, State::Disconnected)
+                    || live
+                        .iter()
+                        .any(|live_output| live_output.name == output.name)
+            })
+    }
+
+    /// Snapshot of the live outputs, which can be applied to restore them
+    pub fn from_live(name: &str, live: &[LiveOutput]) -> Self {
+        let mut layout = Self {
+            name: name.to_string(),
+            ..Self::new()
+        };
+        for live_output in live {
+            layout.add(match (&live_output.mode, live_output.geometry) {
+                (Some(mode), Some(geometry)) => Output {
+                    name: live_output.name.clone(),
+                    mode: mode.clone(),
+                    is_primary: live_output.is_primary,
+                    state: State::Connected,
+                    position: Position::Absolute(Point {
+                        x: geometry.x,
+                        y: geometry.y,
+                    }),
+                    orientation: live_output.orientation.clone(),
+                    ..Output::new()
+                },
+                _ => Output {
+                    name: live_output.name.clone(),
+                    ..Output::new()
+                },
+            });
+        }
+        layout
+    }
+
     pub fn get_xrandr_args(&self) -> Vec<String> {
         // TODO: replace xrandr with static Singletone and use it here
         self.outputs
-            .iter()
-            .map(|(_, output)| output.to_xrandr_output())
+            .values()
+            .map(|output| output.to_xrandr_output())
             .collect::<Vec<String>>()
     }
 }
 
+/// Size and position of an enabled output on the screen, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A connected output as it is configured at the moment, according to `xrandr --query`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveOutput {
+    pub name: String,
+    pub is_primary: bool,
+    /// Mode marked as current, None if the output is turned off
+    pub mode: Option<Mode>,
+    /// None if the output is turned off
+    pub geometry: Option<Geometry>,
+    pub orientation: Orientation,
+}
+
 #[derive(Default)]
 pub struct OutputModes {
     pub resolutions: Vec<Resolution>,
@@ -419,6 +689,13 @@ impl OutputModes {
         map_str(&self.rates)
     }
 
+    /// Whether the output can run the mode, unset resolutions and rates are picked by xrandr
+    pub fn supports(&self, mode: &Mode) -> bool {
+        mode.is_auto()
+            || (self.resolutions.contains(&mode.resolution)
+                && (mode.rate == Rate::default() || self.rates.contains(&mode.rate)))
+    }
+
     fn remove_duplicates(&mut self) {
         self.resolutions = sort_and_filter_unique(&mut self.resolutions);
         self.rates = sort_and_filter_unique(&mut self.rates);
@@ -445,3 +722,201 @@ impl FromStr for OutputModes {
 
     type Err = Error;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(resolution: &str, rate: &str) -> Mode {
+        Mode {
+            resolution: resolution.parse().unwrap(),
+            rate: rate.parse().unwrap(),
+        }
+    }
+
+    fn turned_on(name: &str, mode: Mode, geometry: (u32, u32, i32, i32)) -> LiveOutput {
+        let (width, height, x, y) = geometry;
+        LiveOutput {
+            name: name.to_string(),
+            is_primary: false,
+            mode: Some(mode),
+            geometry: Some(Geometry {
+                width,
+                height,
+                x,
+                y,
+            }),
+            orientation: Orientation::Normal,
+        }
+    }
+
+    fn off(name: &str) -> LiveOutput {
+        LiveOutput {
+            name: name.to_string(),
+            is_primary: false,
+            mode: None,
+            geometry: None,
+            orientation: Orientation::Normal,
+        }
+    }
+
+    fn output(name: &str, state: State, position: Position) -> Output {
+        Output {
+            name: name.to_string(),
+            state,
+            position,
+            ..Output::new()
+        }
+    }
+
+    /// HDMI-1 left of eDP-1, DP-1 turned off
+    fn left() -> Layout {
+        let mut layout = Layout {
+            name: "Left".to_string(),
+            ..Layout::new()
+        };
+        layout.add(Output {
+            is_primary: true,
+            mode: mode("1920x1080", "60"),
+            ..output("eDP-1", State::Connected, Position::Center)
+        });
+        layout.add(output(
+            "HDMI-1",
+            State::Connected,
+            Position::LeftOf("eDP-1".to_string()),
+        ));
+        layout.add(output("DP-1", State::Disconnected, Position::Center));
+        layout
+    }
+
+    fn left_live() -> Vec<LiveOutput> {
+        vec![
+            LiveOutput {
+                is_primary: true,
+                ..turned_on("eDP-1", mode("1920x1080", "60.01"), (1920, 1080, 2560, 0))
+            },
+            turned_on("HDMI-1", mode("2560x1440", "59.95"), (2560, 1440, 0, 0)),
+            off("DP-1"),
+        ]
+    }
+
+    #[test]
+    fn matches_modes() {
+        let full_hd = mode("1920x1080", "60");
+        assert!(full_hd.matches(&mode("1920x1080", "59.96")));
+        assert!(!full_hd.matches(&mode("1920x1080", "50")));
+        assert!(!full_hd.matches(&mode("2560x1440", "60")));
+        // Without a rate any rate matches, without a resolution any mode
+        assert!(mode("1920x1080", "0").matches(&mode("1920x1080", "144")));
+        assert!(Mode::default().matches(&mode("2560x1440", "75")));
+    }
+
+    #[test]
+    fn matches_live_outputs_configured_by_the_layout() {
+        assert!(left().matches_live(&left_live()));
+    }
+
+    #[test]
+    fn rejects_live_outputs_placed_elsewhere() {
+        let mut live = left_live();
+        live[1].geometry = Some(Geometry {
+            width: 2560,
+            height: 1440,
+            x: 1920,
+            y: 0,
+        });
+        live[0].geometry = Some(Geometry {
+            width: 1920,
+            height: 1080,
+            x: 0,
+            y: 0,
+        });
+        assert!(!left().matches_live(&live));
+    }
+
+    #[test]
+    fn rejects_other_modes_orientations_and_primaries() {
+        let mut live = left_live();
+        live[0].mode = Some(mode("1280x720", "60"));
+        assert!(!left().matches_live(&live));
+
+        let mut live = left_live();
+        live[1].orientation = Orientation::Left;
+        assert!(!left().matches_live(&live));
+
+        let mut live = left_live();
+        live[0].is_primary = false;
+        assert!(!left().matches_live(&live));
+    }
+
+    #[test]
+    fn rejects_outputs_turned_on_or_off_otherwise() {
+        let mut live = left_live();
+        live[2] = turned_on("DP-1", mode("1920x1080", "60"), (1920, 1080, 4480, 0));
+        assert!(!left().matches_live(&live));
+
+        let mut live = left_live();
+        live[1] = off("HDMI-1");
+        assert!(!left().matches_live(&live));
+
+        // An output the layout turns on is missing
+        assert!(!left().matches_live(&left_live()[..1]));
+    }
+
+    #[test]
+    fn matches_mirrored_and_absolute_outputs() {
+        let mut layout = Layout::new();
+        layout.add(output(
+            "eDP-1",
+            State::Connected,
+            Position::Absolute(Point { x: 0, y: 0 }),
+        ));
+        layout.add(output(
+            "HDMI-1",
+            State::Duplicated("eDP-1".to_string()),
+            Position::Center,
+        ));
+        let live = vec![
+            turned_on("eDP-1", mode("1920x1080", "60"), (1920, 1080, 0, 0)),
+            turned_on("HDMI-1", mode("1920x1080", "60"), (1920, 1080, 0, 0)),
+        ];
+        assert!(layout.matches_live(&live));
+
+        let mut moved = live.clone();
+        moved[1].geometry = Some(Geometry {
+            width: 1920,
+            height: 1080,
+            x: 1920,
+            y: 0,
+        });
+        assert!(!layout.matches_live(&moved));
+    }
+
+    #[test]
+    fn rejects_layouts_relative_to_outputs_turned_off() {
+        assert_eq!(left().validate(), Ok(()));
+
+        let mut layout = left();
+        layout.add(output(
+            "HDMI-1",
+            State::Connected,
+            Position::RightOf("DP-1".to_string()),
+        ));
+        assert!(layout.validate().is_err());
+
+        let mut layout = left();
+        layout.add(output(
+            "DP-1",
+            State::Duplicated("VGA-1".to_string()),
+            Position::Center,
+        ));
+        assert!(layout.validate().is_err());
+
+        let mut layout = left();
+        layout.add(Output {
+            is_primary: true,
+            ..output("DP-1", State::Connected, Position::Center)
+        });
+        assert!(layout.validate().is_err());
+    }
+}
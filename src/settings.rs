@@ -0,0 +1,132 @@
+/// The `[settings]` table of config.toml, the command line flags take precedence over it
+use crate::fallback::Policy;
+use regex::Regex;
+use serde::{de, Deserializer, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+/// Regex compiled once when the settings are loaded, saved as its source
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl serde::Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Regex::new(&source)
+            .map(Self)
+            .map_err(|error| de::Error::custom(format!("invalid pattern: {}", error)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MenuSettings {
+    /// Executable of the menu, looked up in PATH
+    pub program: String,
+    /// Arguments the menu is run with, the prompt is passed after them
+    pub args: Vec<String>,
+    /// Appended to the name of the layout the outputs are configured with
+    pub check_mark: String,
+}
+
+impl Default for MenuSettings {
+    fn default() -> Self {
+        Self {
+            program: "rofi".to_string(),
+            args: ["-i", "-matching fuzzy", "-dmenu", "-p"]
+                .map(str::to_string)
+                .to_vec(),
+            check_mark: " ✓".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DaemonSettings {
+    /// Seconds the set of connected outputs should stay unchanged before a layout is selected
+    pub settle: u64,
+    /// Interval in milliseconds between two checks of the connected outputs
+    pub poll_interval: u64,
+    /// Maximum number of layouts applied by the daemon per minute
+    pub max_applies: usize,
+    pub fallback: Policy,
+    pub save_fallback: bool,
+    pub ask: bool,
+    pub notify: bool,
+}
+
+impl Default for DaemonSettings {
+    fn default() -> Self {
+        Self {
+            settle: 3,
+            poll_interval: 500,
+            max_applies: 4,
+            fallback: Policy::default(),
+            save_fallback: true,
+            ask: false,
+            notify: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub menu: MenuSettings,
+    pub daemon: DaemonSettings,
+    /// Regex matching the names of laptop panels, which the fallback policies and the lid handle
+    pub internal_output_pattern: Pattern,
+    /// Seconds to confirm a layout applied from the menu before it is reverted, 0 doesn't ask
+    pub rollback_timeout: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            menu: MenuSettings::default(),
+            daemon: DaemonSettings::default(),
+            internal_output_pattern: Pattern(
+                Regex::new(r"^(eDP|LVDS|DSI)").expect("Hardcoded regexp."),
+            ),
+            rollback_timeout: 0,
+        }
+    }
+}
+
+impl Settings {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.daemon.max_applies == 0 {
+            return Err("max_applies must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the output is the panel of a laptop
+    pub fn is_internal_output(&self, output_name: &str) -> bool {
+        self.internal_output_pattern.is_match(output_name)
+    }
+}
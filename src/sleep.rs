@@ -0,0 +1,72 @@
+/// Notices when the machine resumes from suspend, through logind's PrepareForSleep signal
+/// or, without logind, through the wall clock jumping forward between two polls
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// The wall clock keeps running while the machine sleeps, unlike the monotonic one
+const CLOCK_JUMP: Duration = Duration::from_secs(30);
+
+pub struct ResumeWatcher {
+    resumed: Arc<AtomicBool>,
+    last_poll: SystemTime,
+}
+
+impl ResumeWatcher {
+    /// Starts listening for logind's signals in a thread
+    pub fn start() -> Self {
+        let resumed = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&resumed);
+        thread::spawn(move || {
+            if let Err(error) = listen_for_resume(&flag) {
+                eprintln!("Error listening for logind sleep signals: {}", error);
+            }
+        });
+        Self {
+            resumed,
+            last_poll: SystemTime::now(),
+        }
+    }
+
+    /// Returns true once after every resume, expects to be called every `poll_interval`
+    pub fn has_resumed(&mut self, poll_interval: Duration) -> bool {
+        let now = SystemTime::now();
+        let clock_jumped = now
+            .duration_since(self.last_poll)
+            .is_ok_and(|elapsed| elapsed > poll_interval + CLOCK_JUMP);
+        self.last_poll = now;
+        self.resumed.swap(false, Ordering::SeqCst) || clock_jumped
+    }
+}
+
+fn listen_for_resume(resumed: &AtomicBool) -> std::io::Result<()> {
+    let mut monitor = Command::new("gdbus")
+        .args([
+            "monitor",
+            "--system",
+            "--dest",
+            "org.freedesktop.login1",
+            "--object-path",
+            "/org/freedesktop/login1",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(stdout) = monitor.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            // Sent with `true` before suspending and with `false` after resuming
+            if line?.contains("PrepareForSleep (false,)") {
+                resumed.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+    monitor.wait()?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+/// State slam-rs learns while running, kept apart from the user's config.toml
+use crate::{backup, config, edid::Edid, paths};
+use itertools::Itertools;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+const STATE_FILE: &str = "state.toml";
+
+/// What to do when a monitor set none of the saved layouts matches appears again
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "layout")]
+pub enum Choice {
+    Layout(String),
+    Fallback,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct AppState {
+    #[serde(skip_serializing, skip_deserializing)]
+    pub file: PathBuf,
+    /// Name of the layout applied last, preferred when several layouts match the outputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_applied: Option<String>,
+    #[serde(default)]
+    pub setups: HashMap<String, Choice>,
+}
+
+/// Identifies a set of connected outputs regardless of their order, and the monitors on them
+/// by their EDID where it can be read, so other monitors on the same ports are another set
+pub fn setup_key(connected: &[String], edids: &HashMap<String, Edid>) -> String {
+    connected
+        .iter()
+        .sorted()
+        .map(|output_name| match edids.get(output_name) {
+            Some(edid) => format!("{}={}", output_name, edid.identity()),
+            None => output_name.clone(),
+        })
+        .join("+")
+}
+
+/// Remembers the layout applied by hand for the set of outputs,
+/// so the daemon prefers it the next time they are connected
+pub fn learn(setup: &str, layout_name: &str) -> Result<(), config::Error> {
+    AppState::load()?.remember(setup, Choice::Layout(layout_name.to_string()))
+}
+
+/// Records the layout as the one applied last
+pub fn record_applied(layout_name: &str) -> Result<(), config::Error> {
+    AppState::load()?.update(|state| state.last_applied = Some(layout_name.to_string()))
+}
+
+impl AppState {
+    pub fn load() -> Result<Self, config::Error> {
+        let file = paths::state_dir()?.join(STATE_FILE);
+        let mut state = match fs::read_to_string(&file) {
+            Ok(content) => toml_edit::de::from_str::<Self>(&content)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(error.into()),
+        };
+        state.file = file;
+        Ok(state)
+    }
+
+    pub fn choice(&self, setup: &str) -> Option<&Choice> {
+        self.setups.get(setup)
+    }
+
+    pub fn remember(&mut self, setup: &str, choice: Choice) -> Result<(), config::Error> {
+        self.update(|state| {
+            state.setups.insert(setup.to_string(), choice);
+        })
+    }
+
+    pub fn forget(&mut self, setup: &str) -> Result<(), config::Error> {
+        self.update(|state| {
+            state.setups.remove(setup);
+        })
+    }
+
+    /// Changes the state on disk, which another process might have changed since it was loaded
+    fn update(&mut self, change: impl FnOnce(&mut Self)) -> Result<(), config::Error> {
+        let mut state = Self::load()?;
+        change(&mut state);
+        state.save()?;
+        *self = state;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), config::Error> {
+        backup::write_atomic(
+            &self.file,
+            toml_edit::ser::to_string_pretty(self)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+/// Integration with `systemd --user`: readiness notifications and the unit file
+use std::{
+    env, fs, io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    path::PathBuf,
+};
+
+const UNIT_NAME: &str = "slam-rs.service";
+
+/// Sends a state (e.g. `READY=1`) to the service manager, if slam-rs was started by one
+pub fn notify(state: &str) -> io::Result<()> {
+    let socket_path = match env::var("NOTIFY_SOCKET") {
+        Ok(socket_path) if !socket_path.is_empty() => socket_path,
+        _ => return Ok(()),
+    };
+    let address = match socket_path.strip_prefix('@') {
+        Some(abstract_name) => SocketAddr::from_abstract_name(abstract_name)?,
+        None => SocketAddr::from_pathname(&socket_path)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+pub fn unit_file() -> io::Result<String> {
+    Ok(format!(
+        "[Unit]
+Description=Screen Layout Advanced Manager daemon
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+Type=notify
+ExecStart={} --daemon --foreground
+Restart=on-failure
+
+[Install]
+WantedBy=graphical-session.target
+",
+        env::current_exe()?.to_string_lossy()
+    ))
+}
+
+pub fn unit_path() -> io::Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Cannot find config dir"))?
+        .join("systemd/user")
+        .join(UNIT_NAME))
+}
+
+/// Writes the unit file of the user service and returns its path
+pub fn install_unit() -> io::Result<PathBuf> {
+    let path = unit_path()?;
+    if let Some(parent) = path.parent() {
+        fs::DirBuilder::new().recursive(true).create(parent)?;
+    }
+    fs::write(&path, unit_file()?)?;
+    Ok(path)
+}
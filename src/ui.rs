@@ -1,19 +1,25 @@
 /// UI based on dmenu
 use crate::{
     cli::{
-        cmd::CmdResult,
+        cmd::{self, CmdResult},
         dmenu::{Dmenu, Message},
         xrandr::Xrandr,
     },
-    config::{self, LayoutConfig, CHECK_SIGN},
+    config::{self, LayoutConfig},
     exit_err,
+    fallback::Policy,
+    history,
+    ipc::{self, Request},
     screen::{Layout, Orientation, Output, Position, State},
+    state::{AppState, Choice},
     vec_from_enum,
 };
 use std::{
     collections::HashMap,
+    fmt,
     path::{Path, PathBuf},
     process,
+    time::Duration,
 };
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -24,20 +30,21 @@ const PRIMARY_SELECTED: bool = true;
 #[derive(EnumIter)]
 pub enum StartOption {
     ApplyLayout,
+    Revert,
     RemoveLayout,
     NewLayout,
     Exit,
 }
 
-impl ToString for StartOption {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for StartOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
             Self::NewLayout => "New Layout",
             Self::RemoveLayout => "Remove Layout",
             Self::ApplyLayout => "Apply Layout",
+            Self::Revert => "Revert",
             Self::Exit => "Exit",
-        }
-        .to_string()
+        })
     }
 }
 
@@ -48,6 +55,7 @@ impl From<String> for StartOption {
             "Remove Layout" => Self::RemoveLayout,
             "Exit" => Self::Exit,
             "Apply Layout" => Self::ApplyLayout,
+            "Revert" => Self::Revert,
             other => {
                 exit_err!("Unexpected start option: {}", other);
             }
@@ -59,29 +67,50 @@ pub struct UserInterface {
     dmenu: Dmenu,
     xrandr: Xrandr,
     config: LayoutConfig,
+    in_daemon: bool,
+    /// Seconds to confirm an applied layout in, 0 doesn't ask
+    rollback_timeout: u64,
 }
 
 // TODO: add LayoutManager struct which will create/remove/apply layouts
 impl UserInterface {
-    pub fn new(config_path: &Path, dmenu_path: Option<PathBuf>) -> Result<Self, config::Error> {
+    /// The menu and the rollback timeout given here take precedence over the settings
+    pub fn new(
+        config_path: &Path,
+        dmenu_path: Option<PathBuf>,
+        rollback_timeout: Option<u64>,
+    ) -> Result<Self, config::Error> {
+        let config = LayoutConfig::try_from_toml(config_path)?;
         Ok(Self {
-            dmenu: Dmenu::new(dmenu_path, None),
+            dmenu: Dmenu::new(dmenu_path, &config.settings.menu),
             xrandr: Xrandr::default(),
-            config: LayoutConfig::try_from_toml(config_path)?,
+            rollback_timeout: rollback_timeout.unwrap_or(config.settings.rollback_timeout),
+            config,
+            in_daemon: false,
+        })
+    }
+
+    /// Menu opened by the daemon: closing it doesn't exit the process,
+    /// and layouts are applied by the daemon itself
+    pub fn for_daemon(config_path: &Path) -> Result<Self, config::Error> {
+        let ui = Self::new(config_path, None, None)?;
+        Ok(Self {
+            dmenu: ui.dmenu.without_exit_on_cancel(),
+            in_daemon: true,
+            ..ui
         })
     }
 
     fn select_layout_name(&self, layout: &mut Layout) -> CmdResult<()> {
         layout.name = self
-            .dmenu
-            .run_and_fetch_output(
+            .config
+            .strip_check_mark(&self.dmenu.run_and_fetch_output(
                 &Message::new(
-                    &self.config.layout_names(),
+                    &self.layout_names(),
                     "What is the name of a new layout? (created are listed below)",
                 ),
                 false,
-            )?
-            .replace(CHECK_SIGN, "");
+            )?);
         Ok(())
     }
 
@@ -191,17 +220,18 @@ impl UserInterface {
     }
 
     fn does_layout_exist_and_override(&self, layout_name: &str) -> CmdResult<bool> {
-        Ok(!matches!(self.config.get(layout_name), None)
+        Ok(self.config.get(layout_name).is_some()
             && !self.does_override_existing_layout(layout_name)?)
     }
 
-    fn create_layout(&mut self) -> CmdResult<()> {
+    /// Runs the new-layout wizard and returns the name of the saved layout
+    fn create_layout(&mut self) -> CmdResult<Option<String>> {
         let mut output_modes = self.xrandr.get_output_modes()?;
         let outputs_connected = output_modes.keys().cloned().collect::<Vec<String>>();
         if output_modes.is_empty() {
-            return self
-                .dmenu
-                .run(Message::new(&[], "You don't have any monitors connected."));
+            self.dmenu
+                .run(Message::new(&[], "You don't have any monitors connected."))?;
+            return Ok(None);
         }
         let mut relative_outputs = HashMap::new();
         let mut is_primary_selected = PRIMARY_NOT_SELECTED;
@@ -256,22 +286,20 @@ impl UserInterface {
             }
         }
         if layout.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
         self.disconnect_other_monitors(
             &mut layout,
             output_modes
                 .keys()
-                .chain(self.xrandr.list_disconnected_outputs()?.iter())
-                .into_iter(),
+                .chain(self.xrandr.list_disconnected_outputs()?.iter()),
         );
-        self.config
-            .add(&layout)
-            .unwrap_or_else(|error| exit_err!("{}", error));
-        if self.does_apply_new_layout()? {
-            self.config.apply(&layout.name, &self.xrandr)?;
+        // Returned instead of exiting, as the daemon runs the wizard on a thread
+        self.config.add(&layout)?;
+        if !self.in_daemon && self.does_apply_new_layout()? {
+            self.apply(&layout.name)?;
         }
-        Ok(())
+        Ok(Some(layout.name))
     }
 
     fn does_override_existing_layout(&self, layout_name: &str) -> CmdResult<bool> {
@@ -299,9 +327,7 @@ impl UserInterface {
             "Do you really want to remove '{}' layout? This operation will be irreversible!",
             &layout_name
         ))? {
-            self.config
-                .remove(&layout_name)
-                .unwrap_or_else(|error| exit_err!("{}", error));
+            self.config.remove(&layout_name)?;
         }
         Ok(())
     }
@@ -325,31 +351,109 @@ impl UserInterface {
         self.ask_with_confirmation("You don't have any layouts yet. Create one?")
     }
 
+    /// Names of the layouts, the one the outputs are configured with is checked
+    fn layout_names(&self) -> Vec<String> {
+        let last_applied = AppState::load().ok().and_then(|state| state.last_applied);
+        let current = self.xrandr.get_live_outputs().ok().and_then(|live| {
+            self.config
+                .current_layout(&live, last_applied.as_deref())
+                .map(|layout| layout.name.clone())
+        });
+        self.config.layout_names(current.as_deref())
+    }
+
     fn choose_layout(&mut self) -> CmdResult<String> {
         if self.config.is_empty() {
             self.ask_and_create_layout_if_yes()?;
             Ok(String::new())
         } else {
-            let layout_names = self.config.layout_names();
-            Ok(self
-                .dmenu
-                .run_until_output_not_matched(Message::new(&layout_names, "Choose layout:"))?
-                .replace(CHECK_SIGN, ""))
+            let layout_names = self.layout_names();
+            Ok(self.config.strip_check_mark(
+                &self
+                    .dmenu
+                    .run_until_output_not_matched(Message::new(&layout_names, "Choose layout:"))?,
+            ))
+        }
+    }
+
+    /// Lets the daemon apply the layout if it is running, so it won't switch it back
+    fn apply(&mut self, layout_name: &str) -> CmdResult<()> {
+        if self.in_daemon {
+            return history::apply(&self.config, layout_name, &self.xrandr);
+        }
+        match ipc::send(&Request::Apply(layout_name.to_string())) {
+            Ok(_) => {}
+            Err(ipc::Error::NotRunning) => {
+                history::apply_by_hand(&self.config, layout_name, &self.xrandr)?;
+            }
+            Err(error) => exit_err!("{}", error),
+        }
+        self.confirm_or_revert(layout_name)
+    }
+
+    /// Reverts the applied layout unless it is kept before the rollback timeout,
+    /// e.g. when it turned off every output the menu could be seen on
+    fn confirm_or_revert(&mut self, layout_name: &str) -> CmdResult<()> {
+        if self.rollback_timeout == 0 {
+            return Ok(());
+        }
+        let options = ["Keep".to_string(), "Revert".to_string()];
+        let answer = self.dmenu.run_with_timeout(
+            &Message::new(
+                &options,
+                &format!(
+                    "Keep layout {}? It is reverted in {} seconds",
+                    layout_name, self.rollback_timeout
+                ),
+            ),
+            Duration::from_secs(self.rollback_timeout),
+        );
+        match answer {
+            Ok(answer) if answer == options[0] => Ok(()),
+            Ok(_) | Err(cmd::Error::Timeout(_)) | Err(cmd::Error::EmptyOutput) => {
+                println!("Reverting layout {}", layout_name);
+                self.revert()
+            }
+            Err(error) => Err(error),
         }
     }
 
     fn apply_layout(&mut self) -> CmdResult<()> {
         let layout_name = self.choose_layout()?;
-        self.config.apply(&layout_name, &self.xrandr)
+        self.apply(&layout_name)
+    }
+
+    /// Restores the outputs as they were before the last applied layout,
+    /// which doesn't have to be saved as a layout
+    fn revert(&mut self) -> CmdResult<()> {
+        if !self.in_daemon {
+            match ipc::send(&Request::Undo) {
+                Ok(_) => return Ok(()),
+                Err(ipc::Error::NotRunning) => {}
+                Err(ipc::Error::Daemon(message)) => {
+                    eprintln!("{}", message);
+                    return Ok(());
+                }
+                Err(error) => exit_err!("{}", error),
+            }
+        }
+        if history::undo(&self.config, &self.xrandr)?.is_none() {
+            eprintln!("Nothing to undo");
+        }
+        Ok(())
     }
 
     pub fn start(&mut self) -> CmdResult<()> {
         match self.choose_start_option()? {
-            StartOption::NewLayout => self.create_layout(),
+            StartOption::NewLayout => self.create_layout().map(|_| ()),
             StartOption::ApplyLayout => {
                 self.apply_layout()?;
                 process::exit(0);
             }
+            StartOption::Revert => {
+                self.revert()?;
+                process::exit(0);
+            }
             StartOption::RemoveLayout => self.remove_layout(),
             StartOption::Exit => process::exit(0),
         }
@@ -364,4 +468,32 @@ impl UserInterface {
             ))?
             .into())
     }
+
+    /// Asks what to do with a set of connected outputs none of the saved layouts matches,
+    /// returns None if the menu was left without a choice
+    pub fn resolve_unknown_setup(
+        &mut self,
+        connected: &[String],
+        fallback: Policy,
+    ) -> CmdResult<Option<Choice>> {
+        let pick_layout = "Pick existing layout".to_string();
+        let create_layout = "Create new layout".to_string();
+        let use_fallback = format!("Use default ({})", fallback);
+        let options = if self.config.is_empty() {
+            vec![create_layout.clone(), use_fallback]
+        } else {
+            vec![pick_layout.clone(), create_layout.clone(), use_fallback]
+        };
+        let answer = self.select_from_list(
+            &options,
+            &format!("No layout matches {}. What to do?", connected.join(", ")),
+        )?;
+        if answer == pick_layout {
+            Ok(Some(Choice::Layout(self.choose_layout()?)))
+        } else if answer == create_layout {
+            Ok(self.create_layout()?.map(Choice::Layout))
+        } else {
+            Ok(Some(Choice::Fallback))
+        }
+    }
 }
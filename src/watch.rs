@@ -0,0 +1,124 @@
+/// Notices changes of the config through inotify, without polling the file
+use std::{ffi::CString, fs, io, mem::size_of, os::unix::ffi::OsStrExt, path::Path};
+
+const EVENT_BUFFER_SIZE: usize = 4096;
+/// Not defined by this version of libc, see inotify(7)
+const IN_MASK_ADD: u32 = 0x2000_0000;
+
+/// Watches the directories of the config rather than the file itself,
+/// since editors and slam-rs replace the file on save instead of writing to it
+pub struct ConfigWatcher {
+    fd: libc::c_int,
+    file_names: Vec<Vec<u8>>,
+    /// Directories any TOML file of which is a config, e.g. layouts.d
+    dirs: Vec<libc::c_int>,
+}
+
+impl ConfigWatcher {
+    pub fn new(config_path: &Path) -> io::Result<Self> {
+        // SAFETY: inotify_init1 has no preconditions
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut watcher = Self {
+            fd,
+            file_names: Vec::new(),
+            dirs: Vec::new(),
+        };
+        watcher.add(config_path)?;
+        Ok(watcher)
+    }
+
+    /// Watches another config, e.g. the system one, whose directory has to exist
+    pub fn add(&mut self, config_path: &Path) -> io::Result<()> {
+        // A symlinked config is changed through its target
+        let mut paths = vec![config_path.to_path_buf()];
+        if let Ok(target) = fs::canonicalize(config_path) {
+            if target != config_path {
+                paths.push(target);
+            }
+        }
+        for path in paths {
+            self.watch(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Watches the TOML files of the directory, including the ones created later
+    pub fn add_dir(&mut self, dir: &Path) -> io::Result<()> {
+        let wd = self.add_watch(
+            dir,
+            libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM | libc::IN_DELETE,
+        )?;
+        self.dirs.push(wd);
+        Ok(())
+    }
+
+    fn add_watch(&self, dir: &Path, mask: u32) -> io::Result<libc::c_int> {
+        let dir = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        // SAFETY: the descriptor is open and the path is a valid C string.
+        // A directory watched already keeps what it was watched for
+        let wd = unsafe { libc::inotify_add_watch(self.fd, dir.as_ptr(), mask | IN_MASK_ADD) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+
+    fn watch(&mut self, path: &Path) -> io::Result<()> {
+        let (dir, file_name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(file_name)) => (dir, file_name),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Expected path to a file, found {:?}", path),
+                ))
+            }
+        };
+        self.add_watch(dir, libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO)?;
+        self.file_names.push(file_name.as_bytes().to_vec());
+        Ok(())
+    }
+
+    /// Returns true if the config was written since the last call, without blocking
+    pub fn has_changed(&mut self) -> bool {
+        let mut has_changed = false;
+        let mut buffer = [0u8; EVENT_BUFFER_SIZE];
+        loop {
+            // SAFETY: the buffer is valid for its whole length
+            let read = unsafe {
+                libc::read(
+                    self.fd,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+            if read <= 0 {
+                return has_changed;
+            }
+            let mut offset = 0;
+            while offset + size_of::<libc::inotify_event>() <= read as usize {
+                // SAFETY: the kernel writes whole events, each followed by its name
+                let event = unsafe {
+                    (buffer.as_ptr().add(offset) as *const libc::inotify_event).read_unaligned()
+                };
+                let name_start = offset + size_of::<libc::inotify_event>();
+                let name = &buffer[name_start..name_start + event.len as usize];
+                // The name is padded with NUL bytes
+                let name = name.split(|byte| *byte == 0).next().unwrap_or_default();
+                has_changed |= self.file_names.iter().any(|file_name| file_name == name)
+                    || (self.dirs.contains(&event.wd) && name.ends_with(b".toml"));
+                offset = name_start + event.len as usize;
+            }
+        }
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        // SAFETY: the descriptor is owned by the watcher and closed only once
+        unsafe { libc::close(self.fd) };
+    }
+}